@@ -1,4 +1,4 @@
-use client::FileParams;
+use client::{BucketCopyParams, CopyParams, FileParams, MoveParams, ZipParams};
 
 pub async fn insert_single_file(params: FileParams) {
     client::insert_file(params).await;
@@ -7,3 +7,23 @@ pub async fn insert_single_file(params: FileParams) {
 pub async fn list_buckets(uri: &str) {
     client::list_buckets(uri).await;
 }
+
+pub async fn list_files(uri: &str, bucket: &str, directory: Option<&str>) {
+    client::list_files(uri, bucket, directory).await;
+}
+
+pub async fn download_bucket_zip(params: ZipParams) {
+    client::download_bucket_zip(params).await;
+}
+
+pub async fn move_file(params: MoveParams) {
+    client::move_file(params).await;
+}
+
+pub async fn copy_file(params: CopyParams) {
+    client::copy_file(params).await;
+}
+
+pub async fn copy_bucket(params: BucketCopyParams) {
+    client::copy_bucket(params).await;
+}
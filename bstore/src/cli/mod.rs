@@ -19,3 +19,15 @@ pub const LIST_DESCRIPTION: &str = "List objects in bstore";
 
 pub const BUCKET_SUBCOMMAND: &str = "bucket";
 pub const BUCKET_LIST_DESCRIPTION: &str = "List buckets in bstore";
+
+pub const GET_SUBCOMMAND: &str = "get";
+pub const GET_DESCRIPTION: &str = "Get objects from bstore";
+pub const BUCKET_GET_DESCRIPTION: &str = "List bucket's files or download them as zip archive";
+
+pub const MV_SUBCOMMAND: &str = "mv";
+pub const MV_DESCRIPTION: &str = "Rename file or move it into another bucket";
+
+pub const CP_SUBCOMMAND: &str = "cp";
+pub const CP_DESCRIPTION: &str = "Copy objects on server side without uploading them again";
+pub const FILE_COPY_DESCRIPTION: &str = "Copy single file within its bucket or into another one";
+pub const BUCKET_COPY_DESCRIPTION: &str = "Copy bucket's files into another bucket";
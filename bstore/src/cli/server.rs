@@ -1,5 +1,34 @@
 use clap::ArgMatches;
 
-pub async fn run(_cli_matches: &ArgMatches) {
-    server::run().await;
+pub async fn run(cli_matches: &ArgMatches) {
+    if cli_matches.get_flag("ephemeral") {
+        server::run_ephemeral().await;
+    } else if cli_matches.get_flag("migrate-only") {
+        match server::migrate() {
+            Ok(schema) => println!("database schema migrated to version {}", schema.current),
+            Err(e) => {
+                eprintln!("database schema migration failed. Error: {e}");
+                std::process::exit(1);
+            }
+        }
+    } else if cli_matches.get_flag("check-schema") {
+        match server::check_schema() {
+            Ok(schema) if schema.is_up_to_date() => {
+                println!("database schema version {} is up to date", schema.current);
+            }
+            Ok(schema) => {
+                println!(
+                    "database schema version {} is outdated. Expected version {}",
+                    schema.current, schema.latest
+                );
+                std::process::exit(2);
+            }
+            Err(e) => {
+                eprintln!("database schema cannot be checked. Error: {e}");
+                std::process::exit(1);
+            }
+        }
+    } else {
+        server::run().await;
+    }
 }
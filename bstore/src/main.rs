@@ -1,6 +1,9 @@
 use clap::{Command, arg, command, crate_name};
-use cli::client::{insert_single_file, list_buckets};
-use client::FileParams;
+use cli::client::{
+    copy_bucket, copy_file, download_bucket_zip, insert_single_file, list_buckets, list_files,
+    move_file,
+};
+use client::{BucketCopyParams, CopyParams, FileParams, MoveParams, ZipParams};
 
 mod cli;
 
@@ -9,7 +12,24 @@ async fn main() {
     let cli = command!(crate_name!())
         .about(clap::crate_description!())
         .subcommand(Command::new(cli::BUGREPORT_SUBCOMMAND).about(cli::BUGREPORT_DESCRIPTION))
-        .subcommand(Command::new(cli::SERVER_SUBCOMMAND).about(cli::SERVER_DESCRIPTION))
+        .subcommand(
+            Command::new(cli::SERVER_SUBCOMMAND)
+                .about(cli::SERVER_DESCRIPTION)
+                .arg(
+                    arg!(--"migrate-only")
+                        .help("Apply pending database schema migrations and exit without serving requests"),
+                )
+                .arg(
+                    arg!(--"check-schema")
+                        .conflicts_with("migrate-only")
+                        .help("Check whether database schema is up to date and exit. Exit code is non zero if migrations pending"),
+                )
+                .arg(
+                    arg!(--ephemeral)
+                        .conflicts_with_all(["migrate-only", "check-schema"])
+                        .help("Keep all data in memory instead of database file. Data is lost on shutdown"),
+                ),
+        )
         .subcommand(
             Command::new(cli::INSERT_SUBCOMMAND)
                 .about(cli::INSERT_DESCRIPTION)
@@ -42,6 +62,114 @@ async fn main() {
                     Command::new(cli::BUCKET_SUBCOMMAND).about(cli::BUCKET_LIST_DESCRIPTION),
                 ),
         )
+        .subcommand(
+            Command::new(cli::GET_SUBCOMMAND)
+                .about(cli::GET_DESCRIPTION)
+                .arg(arg!(-u --uri <URI>).required(true).help("Bstore URI"))
+                .subcommand(
+                    Command::new(cli::BUCKET_SUBCOMMAND)
+                        .about(cli::BUCKET_GET_DESCRIPTION)
+                        .arg(
+                            arg!(-b --bucket <BUCKET>)
+                                .required(true)
+                                .help("Bucket to get"),
+                        )
+                        .arg(arg!(--zip).help("Download bucket's files as zip archive"))
+                        .arg(
+                            arg!(-p --prefix <PREFIX>)
+                                .required(false)
+                                .requires("zip")
+                                .help("Archive only files which paths start with the prefix"),
+                        )
+                        .arg(
+                            arg!(-o --output <FILE>)
+                                .required(false)
+                                .requires("zip")
+                                .help("Path to archive to create. <BUCKET>.zip by default"),
+                        )
+                        .arg(
+                            arg!(-d --dir <DIR>)
+                                .required(false)
+                                .conflicts_with("zip")
+                                .help("List only the directory's files and subdirectories. Use / for bucket's root"),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new(cli::MV_SUBCOMMAND)
+                .about(cli::MV_DESCRIPTION)
+                .arg(arg!(-u --uri <URI>).required(true).help("Bstore URI"))
+                .arg(
+                    arg!(-b --bucket <BUCKET>)
+                        .required(true)
+                        .help("Bucket of the file to move"),
+                )
+                .arg(
+                    arg!(-s --source <SOURCE>)
+                        .required(true)
+                        .help("Path of the file to move"),
+                )
+                .arg(
+                    arg!(-d --destination <DESTINATION>)
+                        .required(true)
+                        .help("New path of the file"),
+                )
+                .arg(
+                    arg!(-t --target <TARGET_BUCKET>)
+                        .required(false)
+                        .help("Bucket to move the file into. The file stays in its bucket if not set"),
+                ),
+        )
+        .subcommand(
+            Command::new(cli::CP_SUBCOMMAND)
+                .about(cli::CP_DESCRIPTION)
+                .arg(arg!(-u --uri <URI>).required(true).help("Bstore URI"))
+                .subcommand(
+                    Command::new(cli::FILE_SUBCOMMAND)
+                        .about(cli::FILE_COPY_DESCRIPTION)
+                        .arg(
+                            arg!(-b --bucket <BUCKET>)
+                                .required(true)
+                                .help("Bucket of the file to copy"),
+                        )
+                        .arg(
+                            arg!(-s --source <SOURCE>)
+                                .required(true)
+                                .help("Path of the file to copy"),
+                        )
+                        .arg(
+                            arg!(-d --destination <DESTINATION>)
+                                .required(true)
+                                .help("Path of the copy"),
+                        )
+                        .arg(
+                            arg!(-t --target <TARGET_BUCKET>)
+                                .required(false)
+                                .help("Bucket to copy the file into. The file is copied within its bucket if not set"),
+                        )
+                        .arg(arg!(--overwrite).help("Replace the file that already exists at destination")),
+                )
+                .subcommand(
+                    Command::new(cli::BUCKET_SUBCOMMAND)
+                        .about(cli::BUCKET_COPY_DESCRIPTION)
+                        .arg(
+                            arg!(-b --bucket <BUCKET>)
+                                .required(true)
+                                .help("Bucket to copy"),
+                        )
+                        .arg(
+                            arg!(-t --target <TARGET_BUCKET>)
+                                .required(true)
+                                .help("Bucket to copy the files into"),
+                        )
+                        .arg(
+                            arg!(-p --prefix <PREFIX>)
+                                .required(false)
+                                .help("Copy only files which paths start with the prefix"),
+                        )
+                        .arg(arg!(--overwrite).help("Replace the files that already exist in target bucket. They are skipped otherwise")),
+                ),
+        )
         .arg_required_else_help(true)
         .disable_version_flag(true)
         .get_matches();
@@ -72,5 +200,60 @@ async fn main() {
         {
             list_buckets(uri).await;
         }
+    } else if let Some(get_matches) = cli.subcommand_matches(cli::GET_SUBCOMMAND) {
+        let uri = get_matches.get_one::<String>("uri").unwrap();
+        if let Some(bucket_matches) = get_matches.subcommand_matches(cli::BUCKET_SUBCOMMAND) {
+            let bucket = bucket_matches.get_one::<String>("bucket").unwrap();
+            if bucket_matches.get_flag("zip") {
+                let output = bucket_matches
+                    .get_one::<String>("output")
+                    .cloned()
+                    .unwrap_or_else(|| format!("{bucket}.zip"));
+                let params = ZipParams {
+                    uri: uri.clone(),
+                    bucket: bucket.clone(),
+                    prefix: bucket_matches.get_one::<String>("prefix").cloned(),
+                    output,
+                };
+                download_bucket_zip(params).await;
+            } else {
+                let directory = bucket_matches.get_one::<String>("dir");
+                list_files(uri, bucket, directory.map(String::as_str)).await;
+            }
+        }
+    } else if let Some(mv_matches) = cli.subcommand_matches(cli::MV_SUBCOMMAND) {
+        let params = MoveParams {
+            uri: mv_matches.get_one::<String>("uri").unwrap().clone(),
+            bucket: mv_matches.get_one::<String>("bucket").unwrap().clone(),
+            source: mv_matches.get_one::<String>("source").unwrap().clone(),
+            destination: mv_matches.get_one::<String>("destination").unwrap().clone(),
+            target_bucket: mv_matches.get_one::<String>("target").cloned(),
+        };
+        move_file(params).await;
+    } else if let Some(cp_matches) = cli.subcommand_matches(cli::CP_SUBCOMMAND) {
+        let uri = cp_matches.get_one::<String>("uri").unwrap();
+        if let Some(file_matches) = cp_matches.subcommand_matches(cli::FILE_SUBCOMMAND) {
+            let params = CopyParams {
+                uri: uri.clone(),
+                bucket: file_matches.get_one::<String>("bucket").unwrap().clone(),
+                source: file_matches.get_one::<String>("source").unwrap().clone(),
+                destination: file_matches
+                    .get_one::<String>("destination")
+                    .unwrap()
+                    .clone(),
+                target_bucket: file_matches.get_one::<String>("target").cloned(),
+                overwrite: file_matches.get_flag("overwrite"),
+            };
+            copy_file(params).await;
+        } else if let Some(bucket_matches) = cp_matches.subcommand_matches(cli::BUCKET_SUBCOMMAND) {
+            let params = BucketCopyParams {
+                uri: uri.clone(),
+                bucket: bucket_matches.get_one::<String>("bucket").unwrap().clone(),
+                target_bucket: bucket_matches.get_one::<String>("target").unwrap().clone(),
+                prefix: bucket_matches.get_one::<String>("prefix").cloned(),
+                overwrite: bucket_matches.get_flag("overwrite"),
+            };
+            copy_bucket(params).await;
+        }
     }
 }
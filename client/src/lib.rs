@@ -3,14 +3,24 @@
 use std::path::PathBuf;
 
 use comfy_table::{Attribute, Cell, ContentArrangement, Table, presets::UTF8_HORIZONTAL_ONLY};
-use kernel::Bucket;
-use reqwest::Client;
+use kernel::{BlobLink, Bucket, BucketCopy, Destination, File as FileItem, FileCopy, Listing};
+use reqwest::{Client, StatusCode};
 use resource::Resource;
 use tokio::fs::File;
+use tokio::io::AsyncWriteExt;
 use tokio_util::io::ReaderStream;
 
 pub mod resource;
 
+/// The number of files requested at once while listing bucket
+const LIST_PAGE_SIZE: usize = 1000;
+
+/// Separates directories in file paths
+const DIRECTORY_DELIMITER: &str = "/";
+
+/// Response header with the token to get the next page of listing
+const CONTINUATION_TOKEN: &str = "x-continuation-token";
+
 pub struct FileParams {
     pub uri: String,
     pub file: String,
@@ -18,6 +28,38 @@ pub struct FileParams {
     pub new_file_name: Option<String>,
 }
 
+pub struct ZipParams {
+    pub uri: String,
+    pub bucket: String,
+    pub prefix: Option<String>,
+    pub output: String,
+}
+
+pub struct MoveParams {
+    pub uri: String,
+    pub bucket: String,
+    pub source: String,
+    pub destination: String,
+    pub target_bucket: Option<String>,
+}
+
+pub struct CopyParams {
+    pub uri: String,
+    pub bucket: String,
+    pub source: String,
+    pub destination: String,
+    pub target_bucket: Option<String>,
+    pub overwrite: bool,
+}
+
+pub struct BucketCopyParams {
+    pub uri: String,
+    pub bucket: String,
+    pub target_bucket: String,
+    pub prefix: Option<String>,
+    pub overwrite: bool,
+}
+
 pub async fn insert_file(params: FileParams) {
     let path = PathBuf::from(&params.file);
     let file_name = if let Some(new_file_name) = params.new_file_name {
@@ -26,7 +68,7 @@ pub async fn insert_file(params: FileParams) {
         let file_name = path.file_name().unwrap().to_os_string();
         file_name.to_str().unwrap().to_string()
     };
-    let file_url = url_escape::encode_component(&file_name);
+    let file_url = path_url(&file_name);
 
     let mut resource = Resource::new(&params.uri).unwrap();
     resource
@@ -36,11 +78,26 @@ pub async fn insert_file(params: FileParams) {
 
     let error_message = format!("no such file {}", &params.file);
     let f = File::open(&params.file).await.expect(&error_message);
-    let stream = ReaderStream::new(f);
-    let stream = reqwest::Body::wrap_stream(stream);
 
     let client = Client::new();
-    let result = client.post(resource.to_string()).body(stream).send().await;
+    // Content already stored on server isn't sent again
+    let result = match link_stored_content(
+        &client,
+        &params.uri,
+        &params.file,
+        &params.bucket,
+        &file_name,
+    )
+    .await
+    {
+        Ok(Some(linked)) => Ok(linked),
+        Ok(None) => {
+            let stream = ReaderStream::new(f);
+            let stream = reqwest::Body::wrap_stream(stream);
+            client.post(resource.to_string()).body(stream).send().await
+        }
+        Err(e) => Err(e),
+    };
     match result {
         Ok(x) => {
             let status = x.status();
@@ -68,6 +125,64 @@ pub async fn insert_file(params: FileParams) {
     }
 }
 
+/// Percent encodes file path. Slashes are kept so that file path may be nested
+fn path_url(path: &str) -> String {
+    path.split('/')
+        .map(|segment| url_escape::encode_component(segment).into_owned())
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+/// Creates file that references content stored on server if server has the file's content already.
+/// Returns `None` if the content has to be uploaded because server doesn't have it.
+/// Other failures e.g. file path conflict are returned as errors so that content isn't uploaded in vain
+async fn link_stored_content(
+    client: &Client,
+    uri: &str,
+    file: &str,
+    bucket: &str,
+    path: &str,
+) -> Result<Option<reqwest::Response>, reqwest::Error> {
+    let Ok(blake3_hash) = file_hash(PathBuf::from(file)).await else {
+        return Ok(None);
+    };
+    let Some(mut probe) = Resource::new(uri) else {
+        return Ok(None);
+    };
+    probe
+        .append_path("api")
+        .append_path("blob")
+        .append_path(&blake3_hash);
+    let stored = client.head(probe.to_string()).send().await?;
+    if stored.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    stored.error_for_status()?;
+
+    probe.append_path("files");
+    let link = BlobLink {
+        bucket: bucket.to_owned(),
+        path: path.to_owned(),
+        content_type: None,
+    };
+    let linked = client.post(probe.to_string()).json(&link).send().await?;
+    // Content might be deleted after probe so it's uploaded in that case
+    if linked.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    linked.error_for_status().map(Some)
+}
+
+/// Calculates file's BLAKE3 hash the same way server does
+async fn file_hash(path: PathBuf) -> std::io::Result<String> {
+    tokio::task::spawn_blocking(move || {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update_reader(std::fs::File::open(path)?)?;
+        Ok(hasher.finalize().to_string())
+    })
+    .await?
+}
+
 pub async fn list_buckets(uri: &str) {
     let mut resource = Resource::new(uri).unwrap();
     resource.append_path("api/");
@@ -85,11 +200,18 @@ pub async fn list_buckets(uri: &str) {
                     .set_header(vec![
                         Cell::new("Bucket").add_attribute(Attribute::Bold),
                         Cell::new("Files count").add_attribute(Attribute::Bold),
+                        Cell::new("Total size").add_attribute(Attribute::Bold),
+                        Cell::new("Last modified").add_attribute(Attribute::Bold),
                     ]);
 
                 let buckets: Vec<Bucket> = r;
                 for b in buckets {
-                    table.add_row(vec![Cell::new(b.id), Cell::new(b.files_count)]);
+                    table.add_row(vec![
+                        Cell::new(b.id),
+                        Cell::new(b.files_count),
+                        Cell::new(b.total_size),
+                        Cell::new(b.last_modified),
+                    ]);
                 }
                 println!("{table}");
             }
@@ -100,3 +222,329 @@ pub async fn list_buckets(uri: &str) {
         }
     }
 }
+
+/// Lists bucket's files page by page so that huge buckets are read in parts.
+/// Each page is printed as soon as it's received so as not to keep the whole listing in memory.
+/// Only the directory's files and subdirectories are listed if directory is set
+pub async fn list_files(uri: &str, bucket: &str, directory: Option<&str>) {
+    let client = Client::new();
+
+    let directory = directory.map(directory_prefix);
+    let mut cursor: Option<String> = None;
+    let mut first_page = true;
+    loop {
+        let mut resource = Resource::new(uri).unwrap();
+        resource
+            .append_path("api")
+            .append_path(bucket)
+            .append_query("limit", &LIST_PAGE_SIZE.to_string());
+        if let Some(directory) = &directory {
+            resource
+                .append_query("prefix", directory)
+                .append_query("delimiter", DIRECTORY_DELIMITER);
+        }
+        if let Some(cursor) = &cursor {
+            resource.append_query("cursor", cursor);
+        }
+
+        let response = match client.get(resource.to_string()).send().await {
+            Ok(response) => response,
+            Err(e) => {
+                println!("error: {e:?}");
+                return;
+            }
+        };
+        cursor = response
+            .headers()
+            .get(CONTINUATION_TOKEN)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_owned);
+        let listing = if directory.is_some() {
+            response.json::<Listing>().await
+        } else {
+            response.json::<Vec<FileItem>>().await.map(|files| Listing {
+                common_prefixes: vec![],
+                files,
+            })
+        };
+        let listing = match listing {
+            Ok(listing) => listing,
+            Err(e) => {
+                println!("JSON decode error: {e}");
+                return;
+            }
+        };
+        // Header is printed only once so that pages look like single table
+        let mut table = Table::new();
+        table
+            .load_preset(UTF8_HORIZONTAL_ONLY)
+            .set_content_arrangement(ContentArrangement::Dynamic)
+            .set_width(120);
+        if first_page {
+            table.set_header(vec![
+                Cell::new("ID").add_attribute(Attribute::Bold),
+                Cell::new("Path").add_attribute(Attribute::Bold),
+                Cell::new("Size").add_attribute(Attribute::Bold),
+                Cell::new("Content type").add_attribute(Attribute::Bold),
+                Cell::new("Last modified").add_attribute(Attribute::Bold),
+            ]);
+            first_page = false;
+        }
+        for prefix in listing.common_prefixes {
+            table.add_row(vec![
+                Cell::new(""),
+                Cell::new(prefix),
+                Cell::new(""),
+                Cell::new(""),
+                Cell::new(""),
+            ]);
+        }
+        for f in listing.files {
+            table.add_row(vec![
+                Cell::new(f.id),
+                Cell::new(f.path),
+                Cell::new(f.size),
+                Cell::new(f.content_type),
+                Cell::new(f.updated_at),
+            ]);
+        }
+        println!("{table}");
+        if cursor.is_none() {
+            break;
+        }
+    }
+}
+
+/// Renames file or moves it into another bucket. File is found by its path
+/// so that its id has to be known only by server
+pub async fn move_file(params: MoveParams) {
+    let client = Client::new();
+
+    let Some(file) = find_file(&client, &params.uri, &params.bucket, &params.source).await else {
+        return;
+    };
+
+    let mut resource = Resource::new(&params.uri).unwrap();
+    resource
+        .append_path("api")
+        .append_path("file")
+        .append_path(&file.id.to_string())
+        .append_path("move");
+    let destination = Destination {
+        bucket: params.target_bucket,
+        path: params.destination,
+    };
+    match client
+        .post(resource.to_string())
+        .json(&destination)
+        .send()
+        .await
+    {
+        Ok(r) if r.status().is_success() => match r.json::<FileItem>().await {
+            Ok(moved) => println!(
+                "file {} moved to {}/{}. ID: {}",
+                params.source, moved.bucket, moved.path, moved.id
+            ),
+            Err(e) => println!("JSON decode error: {e}"),
+        },
+        Ok(r) => {
+            let status = r.status();
+            let reason = r.text().await.unwrap_or_default();
+            println!(
+                "file {} not moved. Status: {status}. {reason}",
+                params.source
+            );
+        }
+        Err(e) => println!("error: {e:?}"),
+    }
+}
+
+/// Copies file within its bucket or into another one. Content isn't sent again
+/// because the copy references the content already stored
+pub async fn copy_file(params: CopyParams) {
+    let client = Client::new();
+
+    let Some(file) = find_file(&client, &params.uri, &params.bucket, &params.source).await else {
+        return;
+    };
+
+    let mut resource = Resource::new(&params.uri).unwrap();
+    resource
+        .append_path("api")
+        .append_path("file")
+        .append_path(&file.id.to_string())
+        .append_path("copy");
+    let copy = FileCopy {
+        bucket: params.target_bucket,
+        path: params.destination.clone(),
+        overwrite: params.overwrite,
+    };
+    match client.post(resource.to_string()).json(&copy).send().await {
+        Ok(r) if r.status().is_success() => match r.json::<Vec<i64>>().await {
+            Ok(ids) => println!(
+                "file {} copied to {}. ID: {}",
+                params.source,
+                params.destination,
+                ids.first().copied().unwrap_or_default()
+            ),
+            Err(e) => println!("JSON decode error: {e}"),
+        },
+        Ok(r) => {
+            let status = r.status();
+            let reason = r.text().await.unwrap_or_default();
+            println!(
+                "file {} not copied. Status: {status}. {reason}",
+                params.source
+            );
+        }
+        Err(e) => println!("error: {e:?}"),
+    }
+}
+
+/// Copies bucket's files into another bucket on server side so that nothing is downloaded or uploaded
+pub async fn copy_bucket(params: BucketCopyParams) {
+    let mut resource = Resource::new(&params.uri).unwrap();
+    resource
+        .append_path("api")
+        .append_path("bucket")
+        .append_path(&params.bucket)
+        .append_path("copy");
+    let copy = BucketCopy {
+        bucket: params.target_bucket.clone(),
+        prefix: params.prefix,
+        overwrite: params.overwrite,
+    };
+
+    let client = Client::new();
+    match client.post(resource.to_string()).json(&copy).send().await {
+        Ok(r) if r.status().is_success() => match r.json::<Vec<i64>>().await {
+            Ok(ids) => println!(
+                "bucket {} copied into {}. Files copied: {}",
+                params.bucket,
+                params.target_bucket,
+                ids.len()
+            ),
+            Err(e) => println!("JSON decode error: {e}"),
+        },
+        Ok(r) => {
+            let status = r.status();
+            let reason = r.text().await.unwrap_or_default();
+            println!(
+                "bucket {} not copied. Status: {status}. {reason}",
+                params.bucket
+            );
+        }
+        Err(e) => println!("error: {e:?}"),
+    }
+}
+
+/// Gets file's metadata by its path. Prints the reason and returns `None` if file isn't found
+async fn find_file(client: &Client, uri: &str, bucket: &str, path: &str) -> Option<FileItem> {
+    let mut meta = Resource::new(uri).unwrap();
+    meta.append_path("api")
+        .append_path("meta")
+        .append_path(bucket)
+        .append_path(&path_url(path));
+    match client.get(meta.to_string()).send().await {
+        Ok(r) if r.status().is_success() => match r.json().await {
+            Ok(file) => Some(file),
+            Err(e) => {
+                println!("JSON decode error: {e}");
+                None
+            }
+        },
+        Ok(r) => {
+            println!(
+                "file {path} not found in bucket {bucket}. Status: {}",
+                r.status()
+            );
+            None
+        }
+        Err(e) => {
+            println!("error: {e:?}");
+            None
+        }
+    }
+}
+
+/// Directory's path prefix that ends with delimiter. Bucket's root is empty prefix
+fn directory_prefix(directory: &str) -> String {
+    let directory = directory.trim_matches('/');
+    if directory.is_empty() {
+        String::new()
+    } else {
+        format!("{directory}{DIRECTORY_DELIMITER}")
+    }
+}
+
+/// Downloads bucket's files as zip archive. Archive is written into file as it's received
+pub async fn download_bucket_zip(params: ZipParams) {
+    let mut resource = Resource::new(&params.uri).unwrap();
+    resource
+        .append_path("api")
+        .append_path("bucket")
+        .append_path(&params.bucket)
+        .append_path("zip");
+    if let Some(prefix) = &params.prefix {
+        resource.append_query("prefix", prefix);
+    }
+
+    let client = Client::new();
+    let mut response = match client.get(resource.to_string()).send().await {
+        Ok(r) if r.status().is_success() => r,
+        Ok(r) => {
+            println!(
+                "bucket {} not downloaded. Status: {}",
+                params.bucket,
+                r.status()
+            );
+            return;
+        }
+        Err(e) => {
+            println!("error: {e:?}");
+            return;
+        }
+    };
+
+    let error_message = format!("cannot create file {}", &params.output);
+    let mut f = File::create(&params.output).await.expect(&error_message);
+    let mut written = 0;
+    loop {
+        match response.chunk().await {
+            Ok(Some(chunk)) => {
+                f.write_all(&chunk).await.expect(&error_message);
+                written += chunk.len();
+            }
+            Ok(None) => break,
+            Err(e) => {
+                println!("bucket {} download failed. Error: {e}", params.bucket);
+                return;
+            }
+        }
+    }
+    f.flush().await.expect(&error_message);
+    println!(
+        "bucket {} downloaded into {}. Size: {written}",
+        params.bucket, params.output
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case("release/1.2", "release/1.2/" ; "directory")]
+    #[test_case("/release/1.2/", "release/1.2/" ; "slashes")]
+    #[test_case("", "" ; "root")]
+    #[test_case("/", "" ; "root slash")]
+    fn directory_prefix_tests(directory: &str, expected: &str) {
+        // Arrange
+
+        // Act
+        let result = directory_prefix(directory);
+
+        // Assert
+        assert_eq!(result, expected);
+    }
+}
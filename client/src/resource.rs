@@ -52,6 +52,15 @@ impl Resource {
         }
         self
     }
+
+    /// Appends a query parameter to the current URL. Name and value are percent encoded.
+    ///
+    /// # Returns
+    /// * `&mut Self` - The modified instance for method chaining.
+    pub fn append_query(&mut self, name: &str, value: &str) -> &mut Self {
+        self.url.query_pairs_mut().append_pair(name, value);
+        self
+    }
 }
 
 impl fmt::Display for Resource {
@@ -140,4 +149,18 @@ mod tests {
         // Assert
         assert_eq!(r.to_string().as_str(), "http://localhost/x/y");
     }
+
+    #[test_case("http://localhost/x", "p", "a", "http://localhost/x?p=a" ; "simple")]
+    #[test_case("http://localhost/x?p=a", "q", "b", "http://localhost/x?p=a&q=b" ; "second")]
+    #[test_case("http://localhost/x", "p", "a b/c", "http://localhost/x?p=a+b%2Fc" ; "encoded")]
+    fn append_query_tests(base: &str, name: &str, value: &str, expected: &str) {
+        // Arrange
+        let mut r = Resource::new(base).unwrap();
+
+        // Act
+        r.append_query(name, value);
+
+        // Assert
+        assert_eq!(r.to_string(), expected);
+    }
 }
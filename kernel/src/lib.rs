@@ -1,6 +1,7 @@
 #![warn(clippy::unwrap_in_result)]
 #![warn(clippy::unwrap_used)]
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
@@ -14,6 +15,10 @@ pub struct Bucket {
     pub id: String,
     /// Total number of files stored in this bucket
     pub files_count: i64,
+    /// Total size of all files stored in this bucket in bytes
+    pub total_size: i64,
+    /// Time of the most recent change of any file in this bucket
+    pub last_modified: DateTime<Utc>,
 }
 
 /// Represents a file stored in the system.
@@ -32,6 +37,12 @@ pub struct File {
     pub blake3_hash: String,
     /// Size of the file in bytes
     pub size: usize,
+    /// MIME type of the file content
+    pub content_type: String,
+    /// Time the file was added into bucket
+    pub created_at: DateTime<Utc>,
+    /// Time the file was last modified
+    pub updated_at: DateTime<Utc>,
 }
 
 /// Result of a delete operation showing the number of items removed.
@@ -45,3 +56,130 @@ pub struct DeleteResult {
     /// Number of blob storage objects deleted
     pub blobs: usize,
 }
+
+/// Result of a put operation that either creates a file or replaces an existing one's content.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct PutResult {
+    /// Unique numeric identifier of the file created or replaced
+    pub id: i64,
+    /// Whether the file was created (`true`) or existing file's content was replaced (`false`)
+    pub created: bool,
+}
+
+/// Bucket's versioning configuration.
+///
+/// Writes to existing paths of a versioned bucket keep previous content as older versions.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct Versioning {
+    /// Whether previous file versions are kept
+    pub enabled: bool,
+}
+
+/// Represents one of the stored versions of a file's content.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct FileVersion {
+    /// Version number that increases with each write to the file's path
+    pub version: i64,
+    /// BLAKE3 cryptographic hash of the version's content
+    pub blake3_hash: String,
+    /// Size of the version's content in bytes
+    pub size: usize,
+    /// MIME type of the version's content
+    pub content_type: String,
+    /// Time the version was written
+    pub created_at: DateTime<Utc>,
+    /// Whether this version is the file's current content
+    pub current: bool,
+}
+
+/// Represents stored content that files reference by its hash.
+///
+/// The same content is stored only once no matter how many files reference it.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct Blob {
+    /// BLAKE3 cryptographic hash of the content
+    pub blake3_hash: String,
+    /// Size of the content in bytes
+    pub size: usize,
+    /// MIME type of the content taken from the file that stored it first
+    pub content_type: String,
+    /// Time the content was stored first
+    pub created_at: DateTime<Utc>,
+}
+
+/// Request to create a file that references content already stored instead of sending it again.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BlobLink {
+    /// Identifier of the bucket to add the file into
+    pub bucket: String,
+    /// File path inside bucket
+    pub path: String,
+    /// MIME type of the file. Detected by file name and content if it's missing
+    pub content_type: Option<String>,
+}
+
+/// Storage usage and content deduplication statistics.
+///
+/// Logical size counts every file's and its versions content while physical size counts
+/// each unique content only once so their ratio shows how much deduplication saves.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct Stats {
+    /// Number of files
+    pub files_count: i64,
+    /// Sum of all files sizes in bytes including previous file versions
+    pub logical_size: i64,
+    /// Sum of unique contents sizes in bytes including previous file versions
+    pub physical_size: i64,
+    /// Logical size divided by physical one or 1 if nothing is stored
+    pub dedup_ratio: f64,
+    /// The largest files starting from the largest one
+    pub largest_files: Vec<File>,
+    /// Size of the database file in bytes
+    pub database_size: u64,
+    /// Size of the database write-ahead log file in bytes
+    pub wal_size: u64,
+}
+
+/// Directory-style listing of a bucket's level.
+///
+/// Paths are split into levels by a delimiter so a bucket can be browsed like a file tree.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct Listing {
+    /// Paths of the level's subdirectories ending with the delimiter
+    pub common_prefixes: Vec<String>,
+    /// Files of the level itself
+    pub files: Vec<File>,
+}
+
+/// Where a file is moved to.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct Destination {
+    /// Identifier of the bucket to move the file into. The file stays in its bucket if it's missing
+    pub bucket: Option<String>,
+    /// New file path inside bucket
+    pub path: String,
+}
+
+/// Where a file is copied to. Copy references the same content so nothing is uploaded again.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct FileCopy {
+    /// Identifier of the bucket to copy the file into. The file is copied within its bucket if it's missing
+    pub bucket: Option<String>,
+    /// Path of the copy inside bucket
+    pub path: String,
+    /// Whether to replace the file that already exists at the path
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+/// Bucket's files copied into another bucket under the same paths.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BucketCopy {
+    /// Identifier of the bucket to copy the files into
+    pub bucket: String,
+    /// Only files which path starts with the prefix are copied if it's set
+    pub prefix: Option<String>,
+    /// Whether to replace the files that already exist in target bucket. Such files are skipped otherwise
+    #[serde(default)]
+    pub overwrite: bool,
+}
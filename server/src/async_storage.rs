@@ -0,0 +1,132 @@
+use std::io;
+
+use crate::domain::{Backend, StorageError};
+use crate::pool::ConnectionPool;
+
+/// Asynchronous facade over `Storage`. Storage operations do blocking disk I/O
+/// so they are run on the blocking thread pool instead of async runtime workers.
+/// Thus one slow operation doesn't stall unrelated requests served by the same worker
+pub struct AsyncStorage<S> {
+    pool: ConnectionPool<S>,
+}
+
+impl<S> Clone for AsyncStorage<S> {
+    fn clone(&self) -> Self {
+        Self {
+            pool: self.pool.clone(),
+        }
+    }
+}
+
+impl<S: Backend> AsyncStorage<S> {
+    #[must_use]
+    pub fn new(pool: ConnectionPool<S>) -> Self {
+        Self { pool }
+    }
+
+    /// Runs operation that doesn't change storage using one of the pooled readers
+    pub async fn read<T, F>(&self, operation: F) -> io::Result<T>
+    where
+        F: FnOnce(&mut S) -> Result<T, S::Err> + Send + 'static,
+        T: Send + 'static,
+    {
+        let mut reader = self.pool.reader().await;
+        Self::run_blocking(move || operation(&mut reader)).await
+    }
+
+    /// Runs operation that changes storage using the writer.
+    /// Writer is released only when operation completes even if the caller is gone
+    pub async fn write<T, F>(&self, operation: F) -> io::Result<T>
+    where
+        F: FnOnce(&mut S) -> Result<T, S::Err> + Send + 'static,
+        T: Send + 'static,
+    {
+        let mut writer = self.pool.writer().await;
+        Self::run_blocking(move || operation(&mut writer)).await
+    }
+
+    async fn run_blocking<T, F>(operation: F) -> io::Result<T>
+    where
+        F: FnOnce() -> Result<T, S::Err> + Send + 'static,
+        T: Send + 'static,
+    {
+        tokio::task::spawn_blocking(operation).await?.map_err(|e| {
+            // Kind is kept so that handlers can tell client's mistakes apart from failures
+            if e.is_conflict() {
+                io::Error::new(io::ErrorKind::AlreadyExists, e)
+            } else if e.is_not_found() {
+                io::Error::new(io::ErrorKind::NotFound, e)
+            } else {
+                io::Error::other(e)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::Storage;
+    use crate::sqlite::{Mode, Sqlite};
+
+    fn storage() -> Result<(tempfile::TempDir, AsyncStorage<Sqlite>), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let db = dir.path().join("async.db");
+        Sqlite::open(&db, Mode::ReadWrite)?.new_database()?;
+        let storage = AsyncStorage::new(ConnectionPool::open(&db, 2)?);
+        Ok((dir, storage))
+    }
+
+    #[tokio::test]
+    async fn written_data_read() -> Result<(), Box<dyn std::error::Error>> {
+        // Arrange
+        let (_dir, storage) = storage()?;
+        let id = storage
+            .write(|s| {
+                let mut upload = s.begin_upload()?;
+                s.append_upload(&mut upload, b"content")?;
+                s.commit_upload(upload, "file", "bucket", "text/plain")
+            })
+            .await?;
+
+        // Act
+        let file = storage.read(move |s| s.get_file_info(id)).await?;
+
+        // Assert
+        assert_eq!(file.path, "file");
+        assert_eq!(file.size, 7);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn storage_error_returned() -> Result<(), Box<dyn std::error::Error>> {
+        // Arrange
+        let (_dir, storage) = storage()?;
+
+        // Act
+        let result = storage.read(|s| s.get_file_info(1)).await;
+
+        // Assert
+        assert!(result.is_err_and(|e| e.kind() == io::ErrorKind::NotFound));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn conflict_error_returned() -> Result<(), Box<dyn std::error::Error>> {
+        // Arrange
+        let (_dir, storage) = storage()?;
+        let insert = |s: &mut Sqlite| {
+            let mut upload = s.begin_upload()?;
+            s.append_upload(&mut upload, b"content")?;
+            s.commit_upload(upload, "file", "bucket", "text/plain")
+        };
+        storage.write(insert).await?;
+
+        // Act
+        let result = storage.write(insert).await;
+
+        // Assert
+        assert!(result.is_err_and(|e| e.kind() == io::ErrorKind::AlreadyExists));
+        Ok(())
+    }
+}
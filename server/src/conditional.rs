@@ -0,0 +1,203 @@
+use axum::http::{HeaderMap, HeaderName, StatusCode, header};
+use chrono::{DateTime, Utc};
+
+const ANY: &str = "*";
+const WEAK_PREFIX: &str = "W/";
+const HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// Makes strong entity tag from file content hash
+#[must_use]
+pub fn etag(blake3_hash: &str) -> String {
+    format!("\"{blake3_hash}\"")
+}
+
+/// Formats modification time as HTTP date e.g. `Wed, 21 Oct 2015 07:28:00 GMT`
+#[must_use]
+pub fn last_modified(updated_at: DateTime<Utc>) -> String {
+    updated_at.format(HTTP_DATE_FORMAT).to_string()
+}
+
+/// Evaluates `If-Match`, `If-None-Match` and `If-Modified-Since` preconditions of GET/HEAD request
+/// against current entity tag and modification time. Returns the status that must be replied
+/// instead of the resource if any precondition fails
+#[must_use]
+pub fn evaluate(headers: &HeaderMap, etag: &str, updated_at: DateTime<Utc>) -> Option<StatusCode> {
+    if let Some(if_match) = header_value(headers, &header::IF_MATCH) {
+        if !matches(if_match, etag, false) {
+            return Some(StatusCode::PRECONDITION_FAILED);
+        }
+    }
+    if let Some(if_none_match) = header_value(headers, &header::IF_NONE_MATCH) {
+        if matches(if_none_match, etag, true) {
+            return Some(StatusCode::NOT_MODIFIED);
+        }
+        // If-Modified-Since is ignored when entity tags are sent
+        return None;
+    }
+    let since = header_value(headers, &header::IF_MODIFIED_SINCE)
+        .and_then(|v| DateTime::parse_from_rfc2822(v.trim()).ok());
+    if let Some(since) = since {
+        // HTTP dates have seconds precision
+        if updated_at.timestamp() <= since.timestamp() {
+            return Some(StatusCode::NOT_MODIFIED);
+        }
+    }
+    None
+}
+
+/// Checks whether `Range` header should be honored. It's ignored if `If-Range`
+/// validator doesn't match current entity tag so the whole content is sent then
+#[must_use]
+pub fn range_applicable(headers: &HeaderMap, etag: &str) -> bool {
+    match header_value(headers, &header::IF_RANGE) {
+        Some(if_range) => !if_range.starts_with(WEAK_PREFIX) && if_range.trim() == etag,
+        None => true,
+    }
+}
+
+fn header_value<'a>(headers: &'a HeaderMap, name: &HeaderName) -> Option<&'a str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+/// Matches comma separated entity tags list. Weak comparison ignores `W/` prefix
+/// while strong one never matches weak tags
+fn matches(list: &str, etag: &str, weak: bool) -> bool {
+    list.split(',').map(str::trim).any(|candidate| {
+        if candidate == ANY {
+            return true;
+        }
+        match candidate.strip_prefix(WEAK_PREFIX) {
+            Some(tag) => weak && tag == etag,
+            None => candidate == etag,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use test_case::test_case;
+
+    const TAG: &str = "\"abc\"";
+    const MODIFIED: &str = "Wed, 21 Oct 2015 07:28:00 GMT";
+
+    fn modified() -> DateTime<Utc> {
+        DateTime::from_timestamp_secs(1_445_412_480).unwrap_or_default()
+    }
+
+    fn headers(name: HeaderName, value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn etag_quoted() {
+        // Arrange
+
+        // Act
+        let tag = etag("abc");
+
+        // Assert
+        assert_eq!(tag, TAG);
+    }
+
+    #[test]
+    fn evaluate_no_preconditions() {
+        // Arrange
+        let headers = HeaderMap::new();
+
+        // Act
+        let status = evaluate(&headers, TAG, modified());
+
+        // Assert
+        assert_eq!(status, None);
+    }
+
+    #[test_case("\"abc\"", None ; "same")]
+    #[test_case("*", None ; "any")]
+    #[test_case("\"x\", \"abc\"", None ; "in list")]
+    #[test_case("\"x\"", Some(StatusCode::PRECONDITION_FAILED) ; "other")]
+    #[test_case("W/\"abc\"", Some(StatusCode::PRECONDITION_FAILED) ; "weak never matches")]
+    fn evaluate_if_match(value: &'static str, expected: Option<StatusCode>) {
+        // Arrange
+        let headers = headers(header::IF_MATCH, value);
+
+        // Act
+        let status = evaluate(&headers, TAG, modified());
+
+        // Assert
+        assert_eq!(status, expected);
+    }
+
+    #[test_case("\"abc\"", Some(StatusCode::NOT_MODIFIED) ; "same")]
+    #[test_case("*", Some(StatusCode::NOT_MODIFIED) ; "any")]
+    #[test_case("\"x\",\"abc\"", Some(StatusCode::NOT_MODIFIED) ; "in list")]
+    #[test_case("W/\"abc\"", Some(StatusCode::NOT_MODIFIED) ; "weak matches")]
+    #[test_case("\"x\"", None ; "other")]
+    fn evaluate_if_none_match(value: &'static str, expected: Option<StatusCode>) {
+        // Arrange
+        let headers = headers(header::IF_NONE_MATCH, value);
+
+        // Act
+        let status = evaluate(&headers, TAG, modified());
+
+        // Assert
+        assert_eq!(status, expected);
+    }
+
+    #[test]
+    fn last_modified_http_date() {
+        // Arrange
+
+        // Act
+        let date = last_modified(modified());
+
+        // Assert
+        assert_eq!(date, MODIFIED);
+    }
+
+    #[test_case("Wed, 21 Oct 2015 07:28:00 GMT", Some(StatusCode::NOT_MODIFIED) ; "same")]
+    #[test_case("Thu, 22 Oct 2015 07:28:00 GMT", Some(StatusCode::NOT_MODIFIED) ; "later")]
+    #[test_case("Wed, 21 Oct 2015 07:27:59 GMT", None ; "earlier")]
+    #[test_case("yesterday", None ; "invalid")]
+    fn evaluate_if_modified_since(value: &'static str, expected: Option<StatusCode>) {
+        // Arrange
+        let headers = headers(header::IF_MODIFIED_SINCE, value);
+
+        // Act
+        let status = evaluate(&headers, TAG, modified());
+
+        // Assert
+        assert_eq!(status, expected);
+    }
+
+    #[test]
+    fn evaluate_if_modified_since_ignored_with_if_none_match() {
+        // Arrange
+        let mut headers = headers(header::IF_MODIFIED_SINCE, MODIFIED);
+        headers.insert(header::IF_NONE_MATCH, HeaderValue::from_static("\"x\""));
+
+        // Act
+        let status = evaluate(&headers, TAG, modified());
+
+        // Assert
+        assert_eq!(status, None);
+    }
+
+    #[test_case("\"abc\"", true ; "same")]
+    #[test_case("\"x\"", false ; "other")]
+    #[test_case("W/\"abc\"", false ; "weak")]
+    #[test_case("Wed, 21 Oct 2015 07:28:00 GMT", false ; "date")]
+    fn range_applicable_if_range(value: &'static str, expected: bool) {
+        // Arrange
+        let headers = headers(header::IF_RANGE, value);
+
+        // Act
+        let applicable = range_applicable(&headers, TAG);
+
+        // Assert
+        assert_eq!(applicable, expected);
+    }
+}
@@ -0,0 +1,50 @@
+use mime_guess::mime::{self, Mime};
+
+/// Max number of leading content bytes used to detect type by signature
+pub const SIGNATURE_LEN: usize = 8192;
+
+/// Chooses file's MIME type. Declared type is used if it's valid and more specific
+/// than `application/octet-stream`. Otherwise the type is detected by file extension
+/// and then by content signature
+#[must_use]
+pub fn resolve(declared: Option<&str>, path: &str, head: &[u8]) -> String {
+    let declared = declared
+        .and_then(|d| d.trim().parse::<Mime>().ok())
+        .filter(|m| m.essence_str() != mime::APPLICATION_OCTET_STREAM.essence_str());
+    if let Some(declared) = declared {
+        return declared.to_string();
+    }
+    if let Some(guess) = mime_guess::from_path(path).first() {
+        return guess.to_string();
+    }
+    if let Some(kind) = infer::get(head) {
+        return kind.mime_type().to_owned();
+    }
+    mime::APPLICATION_OCTET_STREAM.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+    #[test_case(Some("text/plain"), "file", b"", "text/plain" ; "declared")]
+    #[test_case(Some("text/plain; charset=utf-8"), "file", b"", "text/plain; charset=utf-8" ; "declared with parameters")]
+    #[test_case(Some("application/octet-stream"), "file.pdf", b"", "application/pdf" ; "generic declared")]
+    #[test_case(Some("not a mime"), "file.pdf", b"", "application/pdf" ; "invalid declared")]
+    #[test_case(None, "dir/file.html", b"", "text/html" ; "extension")]
+    #[test_case(None, "image", PNG, "image/png" ; "signature")]
+    #[test_case(None, "file.txt", PNG, "text/plain" ; "extension before signature")]
+    #[test_case(None, "file", b"unknown", "application/octet-stream" ; "unknown")]
+    fn resolve_tests(declared: Option<&str>, path: &str, head: &[u8], expected: &str) {
+        // Arrange
+
+        // Act
+        let content_type = resolve(declared, path, head);
+
+        // Assert
+        assert_eq!(content_type, expected);
+    }
+}
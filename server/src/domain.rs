@@ -1,28 +1,223 @@
 use std::fmt::{Debug, Display};
-use std::io::Read;
+use std::io;
 
-use kernel::{Bucket, DeleteResult, File};
+use chrono::{DateTime, Utc};
+use kernel::{Blob, Bucket, DeleteResult, File, FileVersion, PutResult, Stats};
+
+use crate::content_type::SIGNATURE_LEN;
+use crate::listing::FileQuery;
+
+/// Staged upload that is written into storage by chunks.
+/// Content hash and size are calculated incrementally while chunks are appended
+pub struct Upload {
+    id: i64,
+    hasher: blake3::Hasher,
+    size: u64,
+    head: Vec<u8>,
+}
+
+impl Upload {
+    #[must_use]
+    pub fn new(id: i64) -> Self {
+        Self {
+            id,
+            hasher: blake3::Hasher::new(),
+            size: 0,
+            head: Vec::new(),
+        }
+    }
+
+    /// Storage specific staging identifier
+    #[must_use]
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    /// The number of bytes appended so far
+    #[must_use]
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Leading bytes of the content used to detect it's type
+    #[must_use]
+    pub fn head(&self) -> &[u8] {
+        &self.head
+    }
+
+    /// Updates hash and size using the next content chunk
+    pub fn update(&mut self, data: &[u8]) {
+        self.hasher.update(data);
+        self.size += data.len() as u64;
+        let missing = SIGNATURE_LEN.saturating_sub(self.head.len());
+        self.head
+            .extend_from_slice(&data[..missing.min(data.len())]);
+    }
+
+    /// BLAKE3 hash of all content appended so far
+    #[must_use]
+    pub fn hash(&self) -> String {
+        self.hasher.finalize().to_string()
+    }
+}
 
 pub trait Storage {
     type Err: Debug + Display;
 
     fn new_database(&self) -> Result<(), Self::Err>;
 
-    fn insert_file(&mut self, path: &str, bucket: &str, data: Vec<u8>) -> Result<i64, Self::Err>;
+    /// Starts new staged upload. Content should be added using `append_upload`
+    /// and then either committed or aborted
+    fn begin_upload(&mut self) -> Result<Upload, Self::Err>;
+
+    fn append_upload(&mut self, upload: &mut Upload, data: &[u8]) -> Result<(), Self::Err>;
+
+    /// Creates new file in bucket from staged content and returns it's id.
+    /// Content already stored under the same hash is reused instead of staged one.
+    /// Existing file gets the new version instead if bucket is versioned
+    fn commit_upload(
+        &mut self,
+        upload: Upload,
+        path: &str,
+        bucket: &str,
+        content_type: &str,
+    ) -> Result<i64, Self::Err>;
+
+    /// Creates new file in bucket that references content already stored under the hash
+    /// specified so that content isn't sent again. Fails if no content is stored under the hash
+    fn link_blob(
+        &mut self,
+        blake3_hash: &str,
+        path: &str,
+        bucket: &str,
+        content_type: &str,
+    ) -> Result<i64, Self::Err>;
+
+    /// Creates new file in bucket from staged content or atomically replaces the content of
+    /// the file that already exists at the path. Replaced blob is removed if it isn't used anymore
+    fn put_upload(
+        &mut self,
+        upload: Upload,
+        path: &str,
+        bucket: &str,
+        content_type: &str,
+    ) -> Result<PutResult, Self::Err>;
+
+    fn abort_upload(&mut self, upload: Upload) -> Result<(), Self::Err>;
 
     fn delete_bucket(&mut self, bucket: &str) -> Result<DeleteResult, Self::Err>;
 
-    fn get_buckets(&mut self) -> Result<Vec<Bucket>, Self::Err>;
+    /// Lists buckets. Only buckets with files modified after `modified_since` are listed if it's set
+    fn get_buckets(
+        &mut self,
+        modified_since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Bucket>, Self::Err>;
+
+    /// Lists bucket's files that match the query in the order it specifies
+    fn get_files(&mut self, bucket: &str, query: &FileQuery) -> Result<Vec<File>, Self::Err>;
 
-    fn get_files(&mut self, bucket: &str) -> Result<Vec<File>, Self::Err>;
+    /// Lists distinct path parts of bucket's files that start with the prefix and end
+    /// with the first delimiter after it i.e. the subdirectories of the directory
+    fn get_common_prefixes(
+        &mut self,
+        bucket: &str,
+        prefix: &str,
+        delimiter: &str,
+    ) -> Result<Vec<String>, Self::Err>;
 
     fn get_last_file(&mut self, bucket: &str) -> Result<File, Self::Err>;
 
-    fn get_file_data(&self, id: i64) -> Result<Box<dyn Read + '_>, Self::Err>;
+    /// Reads blob's content starting from the offset specified into the buffer.
+    /// Returns the number of bytes read that is zero if offset is beyond the blob's end
+    fn get_file_data(
+        &self,
+        blake3_hash: &str,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> Result<usize, Self::Err>;
 
     fn get_file_info(&mut self, id: i64) -> Result<File, Self::Err>;
 
+    /// Gets information about content stored under the hash specified
+    /// if any file or file version references it
+    fn get_blob(&mut self, blake3_hash: &str) -> Result<Blob, Self::Err>;
+
+    /// Lists files in all buckets which current content is stored under the hash specified
+    fn get_blob_files(&mut self, blake3_hash: &str) -> Result<Vec<File>, Self::Err>;
+
     fn search_file_info(&mut self, bucket: &str, path: &str) -> Result<File, Self::Err>;
 
+    /// Calculates usage statistics of the bucket specified or the whole storage if bucket isn't set.
+    /// `largest` is the number of the largest files included
+    fn get_stats(&mut self, bucket: Option<&str>, largest: usize) -> Result<Stats, Self::Err>;
+
+    /// Changes file's path and bucket without touching its content. Returns the file moved
+    /// or `None` if another file already exists at the destination
+    fn move_file(&mut self, id: i64, bucket: &str, path: &str) -> Result<Option<File>, Self::Err>;
+
+    /// Creates file at the path of the bucket that references the same content as the file
+    /// specified. Returns the id of the file written or `None` if another file already exists
+    /// at the destination and `overwrite` isn't set
+    fn copy_file(
+        &mut self,
+        id: i64,
+        bucket: &str,
+        path: &str,
+        overwrite: bool,
+    ) -> Result<Option<i64>, Self::Err>;
+
+    /// Copies source bucket's files which paths start with the prefix into target bucket
+    /// under the same paths. Files that already exist in target bucket are replaced
+    /// only if `overwrite` is set. Returns the ids of the files written
+    fn copy_bucket(
+        &mut self,
+        source: &str,
+        target: &str,
+        prefix: Option<&str>,
+        overwrite: bool,
+    ) -> Result<Vec<i64>, Self::Err>;
+
     fn delete_file(&mut self, id: i64) -> Result<DeleteResult, Self::Err>;
+
+    /// Turns keeping of previous content on writes to existing bucket's paths on or off
+    fn set_versioning(&mut self, bucket: &str, enabled: bool) -> Result<(), Self::Err>;
+
+    fn get_versioning(&mut self, bucket: &str) -> Result<bool, Self::Err>;
+
+    /// Lists file's versions starting from the current one
+    fn get_file_versions(&mut self, id: i64) -> Result<Vec<FileVersion>, Self::Err>;
+
+    /// Deletes file's version. If the current version is deleted the previous one becomes current
+    /// and the file itself is deleted together with its only version
+    fn delete_file_version(&mut self, id: i64, version: i64) -> Result<DeleteResult, Self::Err>;
+
+    /// Makes the version's content current by writing it as the new version
+    /// that is returned. Current content is kept as the previous version
+    fn restore_file_version(&mut self, id: i64, version: i64) -> Result<FileVersion, Self::Err>;
 }
+
+/// Storage error that tells failures caused by request apart from the storage's own ones
+/// so that they're reported to client accordingly
+pub trait StorageError: std::error::Error + Send + Sync + 'static {
+    /// Whether the item written is in conflict with the existing one e.g. file path is taken
+    fn is_conflict(&self) -> bool;
+
+    /// Whether the item requested doesn't exist
+    fn is_not_found(&self) -> bool;
+}
+
+impl StorageError for io::Error {
+    fn is_conflict(&self) -> bool {
+        self.kind() == io::ErrorKind::AlreadyExists
+    }
+
+    fn is_not_found(&self) -> bool {
+        self.kind() == io::ErrorKind::NotFound
+    }
+}
+
+/// Storage that can be used by the server. Its operations are run on blocking threads
+/// so it must be movable between threads as well as its errors
+pub trait Backend: Storage<Err: StorageError> + Send + 'static {}
+
+impl<T> Backend for T where T: Storage<Err: StorageError> + Send + 'static {}
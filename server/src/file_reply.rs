@@ -1,23 +1,146 @@
 use axum::{
     body::Body,
-    http::HeaderValue,
+    http::{HeaderValue, StatusCode},
     response::{IntoResponse, Response},
 };
 use kernel::File;
+use serde::Deserialize;
 use utoipa::{
-    ToResponse,
-    openapi::{self, ObjectBuilder, RefOr, ResponseBuilder, content, schema::SchemaType},
+    ToResponse, ToSchema,
+    openapi::{
+        self, ObjectBuilder, RefOr, ResponseBuilder, content, header::HeaderBuilder,
+        schema::SchemaType,
+    },
 };
 
+use crate::conditional;
+
+const BYTES_UNIT: &str = "bytes";
+
+/// Part of file content requested using `Range` header
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ContentRange {
+    /// No range requested or it cannot be handled so the whole content is sent
+    Full,
+    /// Inclusive range of bytes
+    Partial { start: u64, end: u64 },
+    /// Range lies outside the content
+    Unsatisfiable,
+}
+
+impl ContentRange {
+    /// Parses `Range` header value. Only single range `bytes=` requests are supported.
+    /// Malformed values and multiple ranges are ignored and the whole content is sent then
+    #[must_use]
+    pub fn parse(header: Option<&str>, size: u64) -> Self {
+        let Some(spec) = header.and_then(|h| h.trim().strip_prefix(BYTES_UNIT)) else {
+            return ContentRange::Full;
+        };
+        let Some(spec) = spec.trim_start().strip_prefix('=') else {
+            return ContentRange::Full;
+        };
+        if spec.contains(',') {
+            return ContentRange::Full;
+        }
+        let Some((start, end)) = spec.trim().split_once('-') else {
+            return ContentRange::Full;
+        };
+
+        if start.is_empty() {
+            // Suffix range i.e. last N bytes
+            return match end.parse::<u64>() {
+                Ok(0) => ContentRange::Unsatisfiable,
+                Ok(_) if size == 0 => ContentRange::Unsatisfiable,
+                Ok(len) => ContentRange::Partial {
+                    start: size.saturating_sub(len),
+                    end: size - 1,
+                },
+                Err(_) => ContentRange::Full,
+            };
+        }
+
+        let Ok(start) = start.parse::<u64>() else {
+            return ContentRange::Full;
+        };
+        let end = if end.is_empty() {
+            u64::MAX
+        } else {
+            match end.parse::<u64>() {
+                Ok(end) if end >= start => end,
+                _ => return ContentRange::Full,
+            }
+        };
+        if start >= size {
+            return ContentRange::Unsatisfiable;
+        }
+        ContentRange::Partial {
+            start,
+            end: end.min(size - 1),
+        }
+    }
+
+    /// Half-open interval of bytes to read from storage
+    #[must_use]
+    pub fn bounds(&self, size: u64) -> (u64, u64) {
+        match *self {
+            ContentRange::Full => (0, size),
+            ContentRange::Partial { start, end } => (start, end + 1),
+            ContentRange::Unsatisfiable => (0, 0),
+        }
+    }
+}
+
+/// How browsers should handle file content
+#[derive(Deserialize, ToSchema, Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Disposition {
+    /// Content is downloaded and saved as file
+    #[default]
+    Attachment,
+    /// Content is displayed by browser if it's possible
+    Inline,
+}
+
+impl Disposition {
+    fn as_str(self) -> &'static str {
+        match self {
+            Disposition::Attachment => "attachment",
+            Disposition::Inline => "inline",
+        }
+    }
+}
+
+/// File download reply. Body is expected to be a stream
+/// so as not to load the whole file content into memory
 pub struct FileReply {
-    data: Vec<u8>,
+    body: Body,
     file: File,
+    range: ContentRange,
+    disposition: Disposition,
 }
 
 impl FileReply {
     #[must_use]
-    pub fn new(data: Vec<u8>, file: File) -> Self {
-        Self { data, file }
+    pub fn new(body: Body, file: File) -> Self {
+        Self {
+            body,
+            file,
+            range: ContentRange::Full,
+            disposition: Disposition::Attachment,
+        }
+    }
+
+    #[must_use]
+    pub fn with_disposition(mut self, disposition: Disposition) -> Self {
+        self.disposition = disposition;
+        self
+    }
+
+    /// Makes partial content reply. Body must contain only the bytes of the range
+    #[must_use]
+    pub fn with_range(mut self, range: ContentRange) -> Self {
+        self.range = range;
+        self
     }
 
     fn name_from_path(&self) -> &str {
@@ -32,17 +155,65 @@ impl FileReply {
 
 impl IntoResponse for FileReply {
     fn into_response(self) -> Response {
+        let size = self.file.size as u64;
         let file_name = self.name_from_path().to_owned();
-        let mut res = Body::from(self.data).into_response();
-        res.headers_mut().insert(
-            "content-type",
-            HeaderValue::from_static("application/octet-stream"),
-        );
-        let attachment = format!(r#"attachment; filename="{file_name}""#);
-        if let Ok(val) = HeaderValue::from_str(attachment.as_str()) {
+
+        let (status, len, content_range) = match self.range {
+            ContentRange::Full => (StatusCode::OK, size, None),
+            ContentRange::Partial { start, end } => (
+                StatusCode::PARTIAL_CONTENT,
+                end - start + 1,
+                Some(format!("{BYTES_UNIT} {start}-{end}/{size}")),
+            ),
+            ContentRange::Unsatisfiable => (
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                0,
+                Some(format!("{BYTES_UNIT} */{size}")),
+            ),
+        };
+
+        let mut res = if status == StatusCode::RANGE_NOT_SATISFIABLE {
+            Body::empty().into_response()
+        } else {
+            self.body.into_response()
+        };
+        *res.status_mut() = status;
+
+        let content_type = HeaderValue::from_str(&self.file.content_type)
+            .unwrap_or_else(|_| HeaderValue::from_static("application/octet-stream"));
+        res.headers_mut().insert("content-type", content_type);
+        res.headers_mut()
+            .insert("accept-ranges", HeaderValue::from_static(BYTES_UNIT));
+        let disposition = self.disposition.as_str();
+        let disposition = format!(r#"{disposition}; filename="{file_name}""#);
+        if let Ok(val) = HeaderValue::from_str(disposition.as_str()) {
             res.headers_mut().insert("content-disposition", val);
         }
-        let len = self.file.size.to_string();
+        if self.disposition == Disposition::Inline {
+            res.headers_mut().insert(
+                "x-content-type-options",
+                HeaderValue::from_static("nosniff"),
+            );
+            // Content type is declared by uploader so stored HTML or SVG must not
+            // run scripts on the server's origin when it's displayed. Other content
+            // isn't sandboxed because sandbox disables built-in viewers e.g. PDF one
+            if can_run_scripts(&self.file.content_type) {
+                res.headers_mut().insert(
+                    "content-security-policy",
+                    HeaderValue::from_static("sandbox"),
+                );
+            }
+        }
+        if let Ok(val) = HeaderValue::from_str(&conditional::etag(&self.file.blake3_hash)) {
+            res.headers_mut().insert("etag", val);
+        }
+        if let Ok(val) = HeaderValue::from_str(&conditional::last_modified(self.file.updated_at)) {
+            res.headers_mut().insert("last-modified", val);
+        }
+        if let Some(val) = content_range.and_then(|r| HeaderValue::from_str(&r).ok()) {
+            res.headers_mut().insert("content-range", val);
+        }
+        let len = len.to_string();
         if let Ok(val) = HeaderValue::from_str(len.as_str()) {
             res.headers_mut().insert("Content-Length", val);
         }
@@ -51,6 +222,20 @@ impl IntoResponse for FileReply {
     }
 }
 
+/// Whether browser runs scripts of the content displayed i.e. it's HTML or XML document
+fn can_run_scripts(content_type: &str) -> bool {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    matches!(
+        essence.as_str(),
+        "text/html" | "text/xml" | "application/xml"
+    ) || essence.ends_with("+xml")
+}
+
 impl ToResponse<'static> for FileReply {
     fn response() -> (&'static str, RefOr<openapi::Response>) {
         let object_builder = ObjectBuilder::new();
@@ -64,8 +249,61 @@ impl ToResponse<'static> for FileReply {
         (
             "FileReply",
             ResponseBuilder::new()
-                .description("File binary content")
+                .description(
+                    "File binary content of the MIME type stored with the file. \
+                     If single range `Range: bytes=` header is specified \
+                     only the requested part is returned with 206 Partial Content status. \
+                     416 Range Not Satisfiable is returned if range lies outside the content",
+                )
                 .content("application/octet-stream", content)
+                .header(
+                    "Content-Disposition",
+                    HeaderBuilder::new()
+                        .description(Some(
+                            "`attachment` by default or `inline` if requested with file name",
+                        ))
+                        .build(),
+                )
+                .header(
+                    "Content-Security-Policy",
+                    HeaderBuilder::new()
+                        .description(Some(
+                            "`sandbox` for `inline` HTML or XML content so that it cannot run scripts",
+                        ))
+                        .build(),
+                )
+                .header(
+                    "X-Content-Type-Options",
+                    HeaderBuilder::new()
+                        .description(Some("`nosniff` for `inline` content"))
+                        .build(),
+                )
+                .header(
+                    "Accept-Ranges",
+                    HeaderBuilder::new()
+                        .description(Some("Always `bytes`"))
+                        .build(),
+                )
+                .header(
+                    "ETag",
+                    HeaderBuilder::new()
+                        .description(Some("Quoted BLAKE3 hash of the file content"))
+                        .build(),
+                )
+                .header(
+                    "Last-Modified",
+                    HeaderBuilder::new()
+                        .description(Some("Time the file was last modified"))
+                        .build(),
+                )
+                .header(
+                    "Content-Range",
+                    HeaderBuilder::new()
+                        .description(Some(
+                            "Range of the content returned in partial reply e.g. `bytes 0-499/1234`",
+                        ))
+                        .build(),
+                )
                 .build()
                 .into(),
         )
@@ -75,6 +313,7 @@ impl ToResponse<'static> for FileReply {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::DateTime;
     use test_case::test_case;
 
     #[test_case("", "" ; "empty")]
@@ -95,8 +334,11 @@ mod tests {
             bucket: String::new(),
             blake3_hash: String::new(),
             size: 1,
+            content_type: String::new(),
+            created_at: DateTime::default(),
+            updated_at: DateTime::default(),
         };
-        let reply = FileReply::new(Vec::new(), file);
+        let reply = FileReply::new(Body::empty(), file);
 
         // Act
         let name = reply.name_from_path();
@@ -104,4 +346,59 @@ mod tests {
         // Assert
         assert_eq!(name, expected);
     }
+
+    #[test_case("text/html", true ; "html")]
+    #[test_case("text/html; charset=utf-8", true ; "html with charset")]
+    #[test_case("image/svg+xml", true ; "svg")]
+    #[test_case("application/xhtml+xml", true ; "xhtml")]
+    #[test_case("application/xml", true ; "xml")]
+    #[test_case("TEXT/HTML", true ; "upper case")]
+    #[test_case("application/pdf", false ; "pdf")]
+    #[test_case("image/png", false ; "png")]
+    #[test_case("text/plain", false ; "plain text")]
+    fn can_run_scripts_tests(content_type: &str, expected: bool) {
+        // Arrange
+
+        // Act
+        let result = can_run_scripts(content_type);
+
+        // Assert
+        assert_eq!(result, expected);
+    }
+
+    #[test_case(None, ContentRange::Full ; "no header")]
+    #[test_case(Some("bytes=0-99"), ContentRange::Partial { start: 0, end: 99 } ; "first bytes")]
+    #[test_case(Some("bytes=10-19"), ContentRange::Partial { start: 10, end: 19 } ; "middle")]
+    #[test_case(Some("bytes = 10-19"), ContentRange::Partial { start: 10, end: 19 } ; "spaces")]
+    #[test_case(Some("bytes=90-"), ContentRange::Partial { start: 90, end: 99 } ; "open end")]
+    #[test_case(Some("bytes=90-200"), ContentRange::Partial { start: 90, end: 99 } ; "end beyond size")]
+    #[test_case(Some("bytes=-10"), ContentRange::Partial { start: 90, end: 99 } ; "suffix")]
+    #[test_case(Some("bytes=-200"), ContentRange::Partial { start: 0, end: 99 } ; "suffix longer than size")]
+    #[test_case(Some("bytes=100-"), ContentRange::Unsatisfiable ; "start at size")]
+    #[test_case(Some("bytes=-0"), ContentRange::Unsatisfiable ; "empty suffix")]
+    #[test_case(Some("bytes=20-10"), ContentRange::Full ; "end before start")]
+    #[test_case(Some("bytes=0-1,5-6"), ContentRange::Full ; "multiple ranges")]
+    #[test_case(Some("items=0-1"), ContentRange::Full ; "unknown unit")]
+    #[test_case(Some("bytes=a-b"), ContentRange::Full ; "not a number")]
+    #[test_case(Some("bytes=5"), ContentRange::Full ; "no dash")]
+    fn parse_range(header: Option<&str>, expected: ContentRange) {
+        // Arrange
+
+        // Act
+        let range = ContentRange::parse(header, 100);
+
+        // Assert
+        assert_eq!(range, expected);
+    }
+
+    #[test]
+    fn parse_range_of_empty_content() {
+        // Arrange
+
+        // Act
+        let range = ContentRange::parse(Some("bytes=0-"), 0);
+
+        // Assert
+        assert_eq!(range, ContentRange::Unsatisfiable);
+    }
 }
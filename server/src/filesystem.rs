@@ -0,0 +1,387 @@
+use std::fmt::{self, Display};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use kernel::{Blob, Bucket, DeleteResult, File, FileVersion, PutResult, Stats};
+
+use crate::domain::{Storage, StorageError, Upload};
+use crate::listing::FileQuery;
+use crate::sqlite::{Mode, Sqlite};
+
+const STAGING_DIR: &str = "staging";
+
+/// Storage that keeps metadata in SQLite database but blob's content in files
+/// named by content hash i.e. `<root>/ab/cd/<blake3>`. Such files are never changed
+/// once written so they can be backed up incrementally
+pub struct FileSystem {
+    db: Sqlite,
+    root: PathBuf,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Database(rusqlite::Error),
+    Io(io::Error),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Database(e) => write!(f, "{e}"),
+            Error::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Database(e) => Some(e),
+            Error::Io(e) => Some(e),
+        }
+    }
+}
+
+impl StorageError for Error {
+    fn is_conflict(&self) -> bool {
+        match self {
+            Error::Database(e) => e.is_conflict(),
+            Error::Io(_) => false,
+        }
+    }
+
+    /// Missing content file of the blob that exists in database is a failure
+    /// rather than the request for missing item
+    fn is_not_found(&self) -> bool {
+        match self {
+            Error::Database(e) => e.is_not_found(),
+            Error::Io(_) => false,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        Error::Database(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl Storage for FileSystem {
+    type Err = Error;
+
+    fn new_database(&self) -> Result<(), Self::Err> {
+        fs::create_dir_all(self.root.join(STAGING_DIR))?;
+        Ok(self.db.new_database()?)
+    }
+
+    fn begin_upload(&mut self) -> Result<Upload, Self::Err> {
+        let upload = self.db.begin_upload()?;
+        let staged = self.staged_path(upload.id());
+        let created = fs::create_dir_all(self.root.join(STAGING_DIR))
+            .and_then(|()| OpenOptions::new().write(true).create_new(true).open(staged));
+        if let Err(e) = created {
+            self.db.abort_upload(upload)?;
+            return Err(e.into());
+        }
+        Ok(upload)
+    }
+
+    fn append_upload(&mut self, upload: &mut Upload, data: &[u8]) -> Result<(), Self::Err> {
+        let mut staged = OpenOptions::new()
+            .append(true)
+            .open(self.staged_path(upload.id()))?;
+        staged.write_all(data)?;
+        upload.update(data);
+        Ok(())
+    }
+
+    fn commit_upload(
+        &mut self,
+        upload: Upload,
+        path: &str,
+        bucket: &str,
+        content_type: &str,
+    ) -> Result<i64, Self::Err> {
+        self.commit_staged(upload, |db, upload| {
+            db.commit_upload(upload, path, bucket, content_type)
+        })
+    }
+
+    fn link_blob(
+        &mut self,
+        blake3_hash: &str,
+        path: &str,
+        bucket: &str,
+        content_type: &str,
+    ) -> Result<i64, Self::Err> {
+        Ok(self.db.link_blob(blake3_hash, path, bucket, content_type)?)
+    }
+
+    fn put_upload(
+        &mut self,
+        upload: Upload,
+        path: &str,
+        bucket: &str,
+        content_type: &str,
+    ) -> Result<PutResult, Self::Err> {
+        let (result, blobs) = self.commit_staged(upload, |db, upload| {
+            db.replace_file(upload, path, bucket, content_type)
+        })?;
+        self.remove_blobs(&blobs);
+        Ok(result)
+    }
+
+    fn abort_upload(&mut self, upload: Upload) -> Result<(), Self::Err> {
+        match fs::remove_file(self.staged_path(upload.id())) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                tracing::error!("staged file not removed. Error: {e}");
+            }
+            _ => {}
+        }
+        Ok(self.db.abort_upload(upload)?)
+    }
+
+    fn delete_bucket(&mut self, bucket: &str) -> Result<DeleteResult, Self::Err> {
+        let (files, blobs) = self.db.remove_bucket(bucket)?;
+        self.remove_blobs(&blobs);
+        Ok(DeleteResult {
+            files,
+            blobs: blobs.len(),
+        })
+    }
+
+    fn get_buckets(
+        &mut self,
+        modified_since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Bucket>, Self::Err> {
+        Ok(self.db.get_buckets(modified_since)?)
+    }
+
+    fn get_files(&mut self, bucket: &str, query: &FileQuery) -> Result<Vec<File>, Self::Err> {
+        Ok(self.db.get_files(bucket, query)?)
+    }
+
+    fn get_common_prefixes(
+        &mut self,
+        bucket: &str,
+        prefix: &str,
+        delimiter: &str,
+    ) -> Result<Vec<String>, Self::Err> {
+        Ok(self.db.get_common_prefixes(bucket, prefix, delimiter)?)
+    }
+
+    fn get_last_file(&mut self, bucket: &str) -> Result<File, Self::Err> {
+        Ok(self.db.get_last_file(bucket)?)
+    }
+
+    fn get_file_data(
+        &self,
+        blake3_hash: &str,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> Result<usize, Self::Err> {
+        let mut blob = fs::File::open(self.blob_path(blake3_hash))?;
+        blob.seek(SeekFrom::Start(offset))?;
+        Ok(blob.read(buf)?)
+    }
+
+    fn get_file_info(&mut self, id: i64) -> Result<File, Self::Err> {
+        Ok(self.db.get_file_info(id)?)
+    }
+
+    fn get_blob(&mut self, blake3_hash: &str) -> Result<Blob, Self::Err> {
+        Ok(self.db.get_blob(blake3_hash)?)
+    }
+
+    fn get_blob_files(&mut self, blake3_hash: &str) -> Result<Vec<File>, Self::Err> {
+        Ok(self.db.get_blob_files(blake3_hash)?)
+    }
+
+    fn search_file_info(&mut self, bucket: &str, path: &str) -> Result<File, Self::Err> {
+        Ok(self.db.search_file_info(bucket, path)?)
+    }
+
+    fn get_stats(&mut self, bucket: Option<&str>, largest: usize) -> Result<Stats, Self::Err> {
+        Ok(self.db.get_stats(bucket, largest)?)
+    }
+
+    fn move_file(&mut self, id: i64, bucket: &str, path: &str) -> Result<Option<File>, Self::Err> {
+        Ok(self.db.move_file(id, bucket, path)?)
+    }
+
+    fn copy_file(
+        &mut self,
+        id: i64,
+        bucket: &str,
+        path: &str,
+        overwrite: bool,
+    ) -> Result<Option<i64>, Self::Err> {
+        let (ids, blobs) = self
+            .db
+            .write_copies(bucket, &[(id, path.to_owned())], overwrite)?;
+        self.remove_blobs(&blobs);
+        Ok(ids.first().copied())
+    }
+
+    fn copy_bucket(
+        &mut self,
+        source: &str,
+        target: &str,
+        prefix: Option<&str>,
+        overwrite: bool,
+    ) -> Result<Vec<i64>, Self::Err> {
+        let (ids, blobs) = self
+            .db
+            .copy_bucket_files(source, target, prefix, overwrite)?;
+        self.remove_blobs(&blobs);
+        Ok(ids)
+    }
+
+    fn delete_file(&mut self, id: i64) -> Result<DeleteResult, Self::Err> {
+        let (files, blobs) = self.db.remove_file(id)?;
+        self.remove_blobs(&blobs);
+        Ok(DeleteResult {
+            files,
+            blobs: blobs.len(),
+        })
+    }
+
+    fn set_versioning(&mut self, bucket: &str, enabled: bool) -> Result<(), Self::Err> {
+        Ok(self.db.set_versioning(bucket, enabled)?)
+    }
+
+    fn get_versioning(&mut self, bucket: &str) -> Result<bool, Self::Err> {
+        Ok(self.db.get_versioning(bucket)?)
+    }
+
+    fn get_file_versions(&mut self, id: i64) -> Result<Vec<FileVersion>, Self::Err> {
+        Ok(self.db.get_file_versions(id)?)
+    }
+
+    fn delete_file_version(&mut self, id: i64, version: i64) -> Result<DeleteResult, Self::Err> {
+        let (files, blobs) = self.db.remove_file_version(id, version)?;
+        self.remove_blobs(&blobs);
+        Ok(DeleteResult {
+            files,
+            blobs: blobs.len(),
+        })
+    }
+
+    fn restore_file_version(&mut self, id: i64, version: i64) -> Result<FileVersion, Self::Err> {
+        Ok(self.db.restore_file_version(id, version)?)
+    }
+}
+
+impl FileSystem {
+    /// Opens metadata database and uses directory specified as blob files root
+    pub fn open<P: AsRef<Path>, R: AsRef<Path>>(
+        path: P,
+        mode: Mode,
+        root: R,
+    ) -> Result<FileSystem, Error> {
+        Ok(Self {
+            db: Sqlite::open(path, mode)?,
+            root: root.as_ref().to_path_buf(),
+        })
+    }
+
+    /// Removes uploads left by interrupted requests along with their staged files.
+    /// Must not be called while server is serving requests. Returns the number of uploads removed
+    pub fn remove_pending_uploads(&mut self) -> Result<usize, Error> {
+        let removed = self.db.remove_pending_uploads()?;
+        match fs::remove_dir_all(self.root.join(STAGING_DIR)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(removed),
+        }
+    }
+
+    /// Moves staged content into content addressed file unless the same content
+    /// already exists and then writes metadata using the function specified
+    fn commit_staged<T, F>(&mut self, upload: Upload, commit: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut Sqlite, Upload) -> Result<T, rusqlite::Error>,
+    {
+        let staged = self.staged_path(upload.id());
+        let blob = self.blob_path(&upload.hash());
+
+        let placed = match Self::place_blob(&staged, &blob) {
+            Ok(placed) => placed,
+            Err(e) => {
+                self.abort_upload(upload)?;
+                return Err(e.into());
+            }
+        };
+
+        let result = commit(&mut self.db, upload);
+        if result.is_err() && placed {
+            // Content file isn't referenced by metadata so it's useless
+            fs::remove_file(&blob).unwrap_or_default();
+        }
+        Ok(result?)
+    }
+
+    fn staged_path(&self, upload_id: i64) -> PathBuf {
+        self.root.join(STAGING_DIR).join(upload_id.to_string())
+    }
+
+    /// Content addressed path. Two levels of hash prefix directories
+    /// keep the number of files in a single directory reasonable
+    fn blob_path(&self, blake3_hash: &str) -> PathBuf {
+        let level1 = blake3_hash.get(..2).unwrap_or_default();
+        let level2 = blake3_hash.get(2..4).unwrap_or_default();
+        self.root.join(level1).join(level2).join(blake3_hash)
+    }
+
+    /// Moves staged file to blob's path. Staged file is just removed if the same content
+    /// already exists so as not to have duplicates. Returns whether staged file was moved
+    fn place_blob(staged: &Path, blob: &Path) -> io::Result<bool> {
+        if blob.exists() {
+            fs::remove_file(staged)?;
+            return Ok(false);
+        }
+        if let Some(dir) = blob.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::File::open(staged)?.sync_all()?;
+        fs::rename(staged, blob)?;
+        Ok(true)
+    }
+
+    /// Removes blob files after their metadata has been deleted.
+    /// Failures are only logged because metadata is already consistent
+    fn remove_blobs(&self, hashes: &[String]) {
+        for hash in hashes {
+            if let Err(e) = fs::remove_file(self.blob_path(hash)) {
+                tracing::error!("blob file {hash} not removed. Error: {e}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case("abcdef", "ab/cd/abcdef" ; "hash")]
+    #[test_case("abc", "ab/abc" ; "short hash")]
+    fn blob_path_tests(hash: &str, expected: &str) -> Result<(), Error> {
+        // Arrange
+        let storage = FileSystem::open(":memory:", Mode::ReadWrite, "root")?;
+
+        // Act
+        let path = storage.blob_path(hash);
+
+        // Assert
+        assert_eq!(path, Path::new("root").join(expected));
+        Ok(())
+    }
+}
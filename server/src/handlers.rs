@@ -1,31 +1,184 @@
 #![allow(clippy::unused_async)]
-use crate::domain::Storage;
-use crate::file_reply::FileReply;
-use crate::sqlite::Sqlite;
+use crate::async_storage::AsyncStorage;
+use crate::content_type::SIGNATURE_LEN;
+use crate::domain::{Backend, Upload};
+use crate::file_reply::{ContentRange, Disposition, FileReply};
+use crate::listing::{Cursor, FileQuery, SortBy};
+use crate::{conditional, content_type, tar_import, zip_export};
 use axum::Json;
 use axum::body::{Body, Bytes};
-use axum::extract::State;
+use axum::extract::{Query, State};
 use axum::response::{IntoResponse, Response};
-use futures::lock::Mutex;
+use chrono::{DateTime, Utc};
 use futures::{Stream, TryStreamExt};
 use futures_util::StreamExt;
-use kernel::{Bucket, DeleteResult, File};
+use kernel::{
+    Blob, BlobLink, Bucket, BucketCopy, DeleteResult, Destination, File, FileCopy, FileVersion,
+    Listing, PutResult, Stats, Versioning,
+};
+use serde::Deserialize;
 use std::fmt::Display;
-use std::io::{self, Cursor};
-use std::sync::Arc;
+use std::io::{self, Read, Seek};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::runtime::Handle;
 use tokio_util::io::StreamReader;
+use utoipa::IntoParams;
 
 use axum::{
     extract::{Multipart, Path},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode, header},
 };
 
+/// Max size of the blob part read from storage at once while streaming file content
+const READ_CHUNK_SIZE: u64 = 256 * 1024;
+
+/// Max size of the content part written into storage at once while inserting file
+const WRITE_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// File content request options
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ContentParams {
+    /// `inline` to display content in browser or `attachment` (default) to download it
+    disposition: Option<Disposition>,
+}
+
+/// Files listing options
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListParams {
+    /// List only items modified after the time specified in RFC 3339 format e.g. `2024-01-31T10:00:00Z`
+    modified_since: Option<DateTime<Utc>>,
+}
+
+/// Bucket names taken by API routes. Files written into such buckets couldn't be reached
+pub(crate) const RESERVED_BUCKETS: [&str; 5] = ["file", "blob", "bucket", "meta", "stats"];
+
+/// File path that adds zip archive into bucket when it's posted. It's deprecated alias
+/// of bucket's zip insertion served before bucket operations moved to `/api/bucket/`
+const DEPRECATED_ZIP_PATH: &str = "zip";
+
+/// The number of the largest files included into statistics by default
+const LARGEST_FILES: usize = 10;
+
+/// Statistics options
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StatsParams {
+    /// The number of the largest files to include. 10 by default
+    largest: Option<usize>,
+}
+
+/// Header with the token to get the next page of listing if there are more items
+pub const CONTINUATION_TOKEN: &str = "x-continuation-token";
+
+/// Files listing options
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FilesParams {
+    /// List only files modified after the time specified in RFC 3339 format e.g. `2024-01-31T10:00:00Z`
+    modified_since: Option<DateTime<Utc>>,
+    /// List only files which path starts with the prefix
+    prefix: Option<String>,
+    /// List only files which path matches glob pattern e.g. `*.txt`. `*` matches `/` too
+    glob: Option<String>,
+    /// List only files not smaller than the size in bytes
+    min_size: Option<u64>,
+    /// List only files not larger than the size in bytes
+    max_size: Option<u64>,
+    /// Paths levels delimiter e.g. `/` to list bucket like a file tree
+    delimiter: Option<String>,
+    /// Files order. `path` by default
+    sort: Option<SortBy>,
+    /// Max number of files listed. Must be positive. All files are listed if it's not set
+    limit: Option<usize>,
+    /// Continuation token of the previous page got from `x-continuation-token` header
+    cursor: Option<String>,
+}
+
+impl FilesParams {
+    fn is_filtered(&self) -> bool {
+        self.modified_since.is_some()
+            || self.prefix.is_some()
+            || self.glob.is_some()
+            || self.min_size.is_some()
+            || self.max_size.is_some()
+            || self.cursor.is_some()
+    }
+}
+
+/// Files written by the request that adds several files into bucket
+#[derive(Default)]
+struct Ingested {
+    inserted: Vec<i64>,
+    /// Paths of the files skipped because they already exist
+    conflicts: Vec<String>,
+    /// Paths of the files not written because of storage failures with the errors
+    failures: Vec<String>,
+}
+
+impl Ingested {
+    fn add(&mut self, path: &str, result: io::Result<i64>) {
+        match result {
+            Ok(id) => self.inserted.push(id),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                self.conflicts.push(path.to_owned());
+            }
+            // Already logged and the rest of files are still added
+            Err(e) => self.failures.push(format!("{path}: {e}")),
+        }
+    }
+
+    /// Ids of the files added. Server error is reported if any file isn't written
+    /// because of failure and conflict if nothing is added only because all files exist
+    fn reply(self) -> (StatusCode, Response) {
+        if !self.failures.is_empty() {
+            let failures = format!("files not added: {}", self.failures.join(", "));
+            self.failed(&failures)
+        } else if self.inserted.is_empty() && !self.conflicts.is_empty() {
+            (
+                StatusCode::CONFLICT,
+                format!("files already exist: {}", self.conflicts.join(", ")).into_response(),
+            )
+        } else {
+            created(Json(self.inserted))
+        }
+    }
+
+    /// Error that stopped adding files. Ids of the files added before it are reported
+    /// so that client knows the files are added only partially
+    fn interrupted(self, e: &io::Error) -> (StatusCode, Response) {
+        self.failed(&e.to_string())
+    }
+
+    fn failed(self, error: &str) -> (StatusCode, Response) {
+        if self.inserted.is_empty() {
+            internal_server_error(&error)
+        } else {
+            internal_server_error(&format!(
+                "only files {:?} added. Error: {error}",
+                self.inserted
+            ))
+        }
+    }
+}
+
+/// Bucket archive download options
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ZipParams {
+    /// Archive only files which paths start with the prefix specified
+    prefix: Option<String>,
+}
+
 /// Adds several files from multipart form into bucket.
 #[utoipa::path(
     post,
     path = "/api/{bucket}",
     responses(
         (status = 201, description = "Files created successfully", body = [i64]),
+        (status = 400, description = "Bucket name is reserved", body = String),
+        (status = 409, description = "No files added because all of them already exist", body = String),
         (status = 500, description = "Server error", body = String)
     ),
     tag = "buckets",
@@ -33,24 +186,24 @@ use axum::{
         ("bucket" = String, Path, description = "Bucket id")
     ),
 )]
-pub async fn insert_many_from_form(
+pub async fn insert_many_from_form<S: Backend>(
     Path(bucket): Path<String>,
-    State(db): State<Arc<Mutex<Sqlite>>>,
+    State(db): State<AsyncStorage<S>>,
     mut multipart: Multipart,
 ) -> impl IntoResponse {
+    if let Some(rejected) = reserved_bucket(&bucket) {
+        return rejected;
+    }
     tracing::info!("create bucket: {bucket}");
-    let mut inserted: Vec<i64> = vec![];
-    let mut repository = db.lock().await;
+    let mut ingested = Ingested::default();
     while let Ok(Some(field)) = multipart.next_field().await {
         let file_name = field.file_name().unwrap_or_default().to_string();
-        match read_from_stream(field).await {
-            Ok((result, read_bytes)) => {
-                let insert_result = repository.insert_file(&file_name, &bucket, result);
-                if let Some(id) =
-                    log_file_operation_result(insert_result, &file_name, read_bytes as u64)
-                {
-                    inserted.push(id);
-                }
+        let declared = field.content_type().map(str::to_owned);
+        match stage_stream(&db, field).await {
+            Ok(staged) => {
+                let result =
+                    commit_upload(&db, staged, &file_name, &bucket, declared.as_deref()).await;
+                ingested.add(&file_name, result);
             }
             Err(e) => {
                 tracing::error!("{e}");
@@ -59,40 +212,59 @@ pub async fn insert_many_from_form(
         }
     }
 
-    (StatusCode::CREATED, Json(inserted).into_response())
+    ingested.reply()
 }
 
-/// Adds single file into bucket.
+/// Adds single file into bucket. File's MIME type is taken from `Content-Type` header
+/// or detected by file name and content if the header is missing or generic.
+/// Posting to `zip` path adds files from zip archive instead for existing clients.
+/// It's deprecated so use `/api/bucket/{bucket}/zip` or put file with such path
 #[utoipa::path(
     post,
-    path = "/api/{bucket}/{file_name}",
+    path = "/api/{bucket}/{path}",
     tag = "files",
     responses(
         (status = 201, description = "File added into bucket", body = [i64]),
+        (status = 400, description = "Bucket name is reserved", body = String),
+        (status = 409, description = "File already exists at the path", body = String),
         (status = 500, description = "Server error", body = String)
     ),
     params(
         ("bucket" = String, Path, description = "Bucket id"),
-        ("file_name" = String, Path, description = "File path inside bucket")
+        ("path" = String, Path, description = "File path inside bucket. May contain slashes"),
+        ("Content-Type" = Option<String>, Header, description = "MIME type of the file")
     ),
 )]
-pub async fn insert_file(
+pub async fn insert_file<S: Backend>(
     Path((bucket, file_name)): Path<(String, String)>,
-    State(db): State<Arc<Mutex<Sqlite>>>,
+    State(db): State<AsyncStorage<S>>,
+    headers: HeaderMap,
     body: Body,
 ) -> Result<impl IntoResponse, String> {
-    match read_from_stream(body.into_data_stream()).await {
-        Ok((result, read_bytes)) => {
-            let mut inserted: Vec<i64> = vec![];
+    if file_name == DEPRECATED_ZIP_PATH {
+        tracing::warn!("deprecated zip insertion into bucket {bucket}");
+        let reply = insert_zipped_bucket(Path(bucket), State(db), body)
+            .await
+            .into_response();
+        return Ok((reply.status(), reply));
+    }
+    if let Some(rejected) = reserved_bucket(&bucket) {
+        return Ok(rejected);
+    }
+    let declared = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok());
+    match stage_stream(&db, body.into_data_stream()).await {
+        Ok(staged) => {
             // Plain file branch
-            let mut repository = db.lock().await;
-            let insert_result = repository.insert_file(&file_name, &bucket, result);
-            if let Some(id) =
-                log_file_operation_result(insert_result, &file_name, read_bytes as u64)
-            {
-                inserted.push(id);
+            match commit_upload(&db, staged, &file_name, &bucket, declared).await {
+                Ok(id) => Ok(created(Json(vec![id]))),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok((
+                    StatusCode::CONFLICT,
+                    format!("file {bucket}/{file_name} already exists").into_response(),
+                )),
+                Err(e) => Ok(internal_server_error(&e)),
             }
-            Ok(created(Json(inserted)))
         }
         Err(e) => {
             tracing::error!("{e}");
@@ -101,75 +273,333 @@ pub async fn insert_file(
     }
 }
 
+/// Adds files from tar archive into bucket. Archive may be compressed by gzip or zstd
+/// that is detected automatically. Entries are streamed into storage as archive is received.
+/// Only regular files are added and other entries like directories or links are skipped
+#[utoipa::path(
+    post,
+    path = "/api/bucket/{bucket}/tar",
+    tag = "buckets",
+    responses(
+        (status = 201, description = "Files added into bucket", body = [i64]),
+        (status = 400, description = "Bucket name is reserved", body = String),
+        (status = 409, description = "No files added because all of them already exist", body = String),
+        (status = 500, description = "Server error or archive is broken. Files added before it are listed", body = String)
+    ),
+    params(
+        ("bucket" = String, Path, description = "Bucket id"),
+    ),
+)]
+pub async fn insert_tarred_bucket<S: Backend>(
+    Path(bucket): Path<String>,
+    State(db): State<AsyncStorage<S>>,
+    body: Body,
+) -> Response {
+    if let Some(rejected) = reserved_bucket(&bucket) {
+        return rejected.into_response();
+    }
+    let body_with_io_error = body.into_data_stream().map_err(io::Error::other);
+    let reader = match tar_import::decompress(StreamReader::new(body_with_io_error)).await {
+        Ok(reader) => reader,
+        Err(e) => {
+            tracing::error!("{e}");
+            return internal_server_error(&e).into_response();
+        }
+    };
+
+    let mut archive = tokio_tar::Archive::new(reader);
+    let mut entries = match archive.entries() {
+        Ok(entries) => entries,
+        Err(e) => {
+            tracing::error!("{e}");
+            return internal_server_error(&e).into_response();
+        }
+    };
+
+    let mut ingested = Ingested::default();
+    while let Some(entry) = entries.next().await {
+        let mut entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                // Nothing can be read after broken entry
+                tracing::error!("tar entry not read. Error: {e}");
+                return ingested.interrupted(&e).into_response();
+            }
+        };
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let Some(path) = entry.path().ok().and_then(|p| tar_import::entry_path(&p)) else {
+            continue;
+        };
+        let size = match entry.header().size() {
+            Ok(size) => size,
+            Err(e) => {
+                tracing::error!("tar entry {path} size not read. Error: {e}");
+                return ingested.interrupted(&e).into_response();
+            }
+        };
+
+        match stage_upload(&db, &mut entry).await {
+            // Entry's content just ends if archive is truncated in the middle of it
+            Ok((upload, _)) if upload.size() != size => {
+                let e = io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("tar entry {path} truncated"),
+                );
+                tracing::error!("{e}");
+                return ingested.interrupted(&e).into_response();
+            }
+            Ok(staged) => {
+                let result = commit_upload(&db, staged, &path, &bucket, None).await;
+                ingested.add(&path, result);
+            }
+            Err(e) => {
+                tracing::error!("Tar file copy error: {e}");
+                return ingested.interrupted(&e).into_response();
+            }
+        }
+    }
+
+    ingested.reply().into_response()
+}
+
+/// Downloads bucket's files as zip archive that is built on the fly.
+/// Files are archived under their paths so the archive can be inserted back as is
+#[utoipa::path(
+    get,
+    path = "/api/bucket/{bucket}/zip",
+    tag = "buckets",
+    responses(
+        (status = 200, description = "Zip archive of bucket's files", content_type = "application/zip", body = Vec<u8>),
+        (status = 404, description = "Bucket not found or no files match the prefix", body = String),
+        (status = 500, description = "Server error", body = String)
+    ),
+    params(
+        ("bucket" = String, Path, description = "Bucket id"),
+        ZipParams
+    ),
+)]
+pub async fn get_zipped_bucket<S: Backend>(
+    Path(bucket): Path<String>,
+    State(db): State<AsyncStorage<S>>,
+    Query(params): Query<ZipParams>,
+) -> Response {
+    let id = bucket.clone();
+    let query = FileQuery {
+        prefix: params.prefix,
+        ..FileQuery::default()
+    };
+    let files = match db.read(move |s| s.get_files(&id, &query)).await {
+        Ok(files) => files,
+        Err(e) => {
+            tracing::error!("bucket {bucket} files not read. Error: {e}");
+            return internal_server_error(&e).into_response();
+        }
+    };
+    if files.is_empty() {
+        return (
+            StatusCode::NOT_FOUND,
+            format!("no files in bucket {bucket}"),
+        )
+            .into_response();
+    }
+    tracing::info!("bucket: {bucket} zipped files: {}", files.len());
+
+    let disposition = format!("attachment; filename=\"{bucket}.zip\"");
+    (
+        [
+            (header::CONTENT_TYPE, "application/zip".to_owned()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        Body::from_stream(zip_export::zip_stream(db, files)),
+    )
+        .into_response()
+}
+
+/// Adds file into bucket that references content already stored under the hash specified
+/// so that the same content isn't sent again. Use `HEAD /api/blob/{blake3_hash}` to check
+/// whether the content is stored
+#[utoipa::path(
+    post,
+    path = "/api/blob/{blake3_hash}/files",
+    tag = "blobs",
+    request_body = BlobLink,
+    responses(
+        (status = 201, description = "File added into bucket", body = [i64]),
+        (status = 400, description = "Bucket name is reserved", body = String),
+        (status = 404, description = "Content not found", body = String),
+        (status = 409, description = "File already exists at the path", body = String),
+        (status = 500, description = "Server error", body = String)
+    ),
+    params(
+        ("blake3_hash" = String, Path, description = "BLAKE3 hash of the content"),
+    ),
+)]
+pub async fn link_file<S: Backend>(
+    Path(blake3_hash): Path<String>,
+    State(db): State<AsyncStorage<S>>,
+    Json(link): Json<BlobLink>,
+) -> Response {
+    let BlobLink {
+        bucket,
+        path: file_name,
+        content_type: declared,
+    } = link;
+    if let Some(rejected) = reserved_bucket(&bucket) {
+        return rejected.into_response();
+    }
+    let hash = blake3_hash.clone();
+    let head = db
+        .read(move |s| {
+            let blob = s.get_blob(&hash)?;
+            let mut head = vec![0u8; SIGNATURE_LEN.min(blob.size)];
+            let read = s.get_file_data(&hash, 0, &mut head)?;
+            head.truncate(read);
+            Ok(head)
+        })
+        .await;
+    let head = match head {
+        Ok(head) => head,
+        Err(e) => {
+            tracing::error!("blob {blake3_hash} not read. Error: {e}");
+            return storage_error(&e).into_response();
+        }
+    };
+
+    let content_type = content_type::resolve(declared.as_deref(), &file_name, &head);
+    let path = file_name.clone();
+    let target = format!("{bucket}/{file_name}");
+    match db
+        .write(move |s| s.link_blob(&blake3_hash, &path, &bucket, &content_type))
+        .await
+    {
+        Ok(id) => {
+            tracing::info!("file: {} linked file id: {}", file_name, id);
+            created(Json(vec![id])).into_response()
+        }
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => (
+            StatusCode::CONFLICT,
+            format!("file {target} already exists"),
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("file '{}' not linked. Error: {}", file_name, e);
+            internal_server_error(&e).into_response()
+        }
+    }
+}
+
+/// Creates file or atomically replaces the content of the file that already exists at the path.
+/// File's MIME type is taken from `Content-Type` header or detected by file name and content
+#[utoipa::path(
+    put,
+    path = "/api/{bucket}/{path}",
+    tag = "files",
+    responses(
+        (status = 201, description = "File created", body = PutResult),
+        (status = 200, description = "File content replaced", body = PutResult),
+        (status = 400, description = "Bucket name is reserved", body = String),
+        (status = 500, description = "Server error", body = String)
+    ),
+    params(
+        ("bucket" = String, Path, description = "Bucket id"),
+        ("path" = String, Path, description = "File path inside bucket. May contain slashes"),
+    ),
+)]
+pub async fn put_file<S: Backend>(
+    Path((bucket, file_name)): Path<(String, String)>,
+    State(db): State<AsyncStorage<S>>,
+    headers: HeaderMap,
+    body: Body,
+) -> Response {
+    if let Some(rejected) = reserved_bucket(&bucket) {
+        return rejected.into_response();
+    }
+    let declared = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok());
+    let (upload, guard) = match stage_stream(&db, body.into_data_stream()).await {
+        Ok(staged) => staged,
+        Err(e) => {
+            tracing::error!("{e}");
+            return internal_server_error(&e).into_response();
+        }
+    };
+    let size = upload.size();
+    let content_type = content_type::resolve(declared, &file_name, upload.head());
+    let path = file_name.clone();
+    match db
+        .write(move |s| {
+            guard.disarm();
+            s.put_upload(upload, &path, &bucket, &content_type)
+        })
+        .await
+    {
+        Ok(result) => {
+            tracing::info!(
+                "file: {} read: {} file id: {} created: {}",
+                file_name,
+                size,
+                result.id,
+                result.created
+            );
+            let status = if result.created {
+                StatusCode::CREATED
+            } else {
+                StatusCode::OK
+            };
+            (status, Json(result)).into_response()
+        }
+        Err(e) => {
+            tracing::error!("file '{}' not put. Error: {}", file_name, e);
+            internal_server_error(&e).into_response()
+        }
+    }
+}
+
 /// Adds several files from zip into bucket.
 #[utoipa::path(
     post,
-    path = "/api/{bucket}/zip",
+    path = "/api/bucket/{bucket}/zip",
     tag = "buckets",
     responses(
         (status = 201, description = "Files added into bucket", body = [i64]),
+        (status = 400, description = "Bucket name is reserved", body = String),
+        (status = 409, description = "No files added because all of them already exist", body = String),
         (status = 500, description = "Server error", body = String)
     ),
     params(
         ("bucket" = String, Path, description = "Bucket id"),
     ),
 )]
-pub async fn insert_zipped_bucket(
+pub async fn insert_zipped_bucket<S: Backend>(
     Path(bucket): Path<String>,
-    State(db): State<Arc<Mutex<Sqlite>>>,
+    State(db): State<AsyncStorage<S>>,
     body: Body,
 ) -> Result<impl IntoResponse, String> {
-    match read_from_stream(body.into_data_stream()).await {
-        Ok((data, _)) => {
-            let mut repository = db.lock().await;
-            let mut inserted: Vec<i64> = vec![];
-            // Zip archive branch
-            let buff = Cursor::new(data);
-
-            let zip_result = zip::ZipArchive::new(buff);
-
-            match zip_result {
-                Ok(mut archive) => {
-                    for i in 0..archive.len() {
-                        match archive.by_index(i) {
-                            Ok(mut zip_file) => {
-                                let outpath = zip_file.mangled_name();
-                                let Some(outpath) = outpath.to_str() else {
-                                    continue;
-                                };
-
-                                let Ok(capacity) = usize::try_from(zip_file.size()) else {
-                                    continue;
-                                };
-                                let mut writer: Vec<u8> = Vec::with_capacity(capacity);
-                                match std::io::copy(&mut zip_file, &mut writer) {
-                                    Ok(r) => {
-                                        let insert_result =
-                                            repository.insert_file(outpath, &bucket, writer);
-                                        if let Some(id) =
-                                            log_file_operation_result(insert_result, outpath, r)
-                                        {
-                                            inserted.push(id);
-                                        }
-                                    }
-                                    Err(e) => {
-                                        tracing::error!("Zip file copy error: {e}");
-                                    }
-                                }
-                            }
-                            Err(e) => {
-                                tracing::error!("file not extracted. Error: {:#?}", e);
-                            }
-                        }
-                    }
-                }
-                Err(e) => {
-                    tracing::error!("{:#?}", e);
-                    return Ok(internal_server_error(&e));
-                }
-            }
+    if let Some(rejected) = reserved_bucket(&bucket) {
+        return Ok(rejected);
+    }
+    // Zip central directory is at the end of archive so it has to be seekable
+    let archive = match spool_stream(body.into_data_stream()).await {
+        Ok(f) => f,
+        Err(e) => {
+            tracing::error!("{e}");
+            return Ok(internal_server_error(&e));
+        }
+    };
 
-            Ok(created(Json(inserted)))
+    // Entries are inflated synchronously so that it's done on blocking thread
+    let runtime = Handle::current();
+    let extracted =
+        tokio::task::spawn_blocking(move || insert_zip_entries(&runtime, &db, archive, &bucket))
+            .await;
+    match extracted {
+        Ok(Ok(ingested)) => Ok(ingested.reply()),
+        Ok(Err(e)) => {
+            tracing::error!("{:#?}", e);
+            Ok(internal_server_error(&e))
         }
         Err(e) => {
             tracing::error!("{e}");
@@ -178,6 +608,46 @@ pub async fn insert_zipped_bucket(
     }
 }
 
+/// Adds zip archive's files into bucket. Must be run on blocking thread because entries
+/// are read synchronously while storage operations are awaited using the runtime specified
+fn insert_zip_entries<S: Backend>(
+    runtime: &Handle,
+    db: &AsyncStorage<S>,
+    archive: std::fs::File,
+    bucket: &str,
+) -> zip::result::ZipResult<Ingested> {
+    let mut archive = zip::ZipArchive::new(archive)?;
+    let mut ingested = Ingested::default();
+    for i in 0..archive.len() {
+        match archive.by_index(i) {
+            Ok(zip_file) => {
+                let outpath = zip_file.mangled_name();
+                let Some(outpath) = outpath.to_str().map(str::to_owned) else {
+                    continue;
+                };
+
+                match stage_blocking(runtime, db, zip_file) {
+                    Ok(staged) => {
+                        let result =
+                            runtime.block_on(commit_upload(db, staged, &outpath, bucket, None));
+                        ingested.add(&outpath, result);
+                    }
+                    Err(e) => {
+                        tracing::error!("Zip file copy error: {e}");
+                        ingested.add(&outpath, Err(e));
+                    }
+                }
+            }
+            Err(e) => {
+                // Entries are independent so the rest of them are still added
+                tracing::error!("file not extracted. Error: {:#?}", e);
+                ingested.add(&format!("entry {i}"), Err(io::Error::other(e)));
+            }
+        }
+    }
+    Ok(ingested)
+}
+
 /// Deletes whole bucket with all it's files
 #[utoipa::path(
     delete,
@@ -191,12 +661,12 @@ pub async fn insert_zipped_bucket(
         ("bucket" = String, Path, description = "Bucket id")
     ),
 )]
-pub async fn delete_bucket(
+pub async fn delete_bucket<S: Backend>(
     Path(bucket): Path<String>,
-    State(db): State<Arc<Mutex<Sqlite>>>,
+    State(db): State<AsyncStorage<S>>,
 ) -> Result<impl IntoResponse, String> {
-    let mut repository = db.lock().await;
-    let delete_result = repository.delete_bucket(&bucket);
+    let delete_bucket = bucket.clone();
+    let delete_result = db.write(move |s| s.delete_bucket(&delete_bucket)).await;
     let result = match delete_result {
         Ok(deleted) => {
             tracing::info!(
@@ -229,46 +699,180 @@ pub async fn delete_bucket(
     responses(
         (status = 200, description = "List all buckets successfully", body = [Bucket]),
     ),
+    params(ListParams),
 )]
-pub async fn get_buckets(
-    State(db): State<Arc<Mutex<Sqlite>>>,
+pub async fn get_buckets<S: Backend>(
+    State(db): State<AsyncStorage<S>>,
+    Query(params): Query<ListParams>,
 ) -> Result<impl IntoResponse, String> {
-    let mut repository = db.lock().await;
-    let result = repository.get_buckets().unwrap_or_default();
+    let result = db
+        .read(move |s| s.get_buckets(params.modified_since))
+        .await
+        .unwrap_or_default();
     Ok(Json(result))
 }
 
-/// Lists all files from a bucket
+/// Lists bucket's files. Files can be filtered and listed by pages. If there are more files
+/// than `limit` the token to get the next page is returned in `x-continuation-token` header.
+/// If `delimiter` is set the bucket is listed like a file tree: only the files of the `prefix`
+/// level are listed along with the level's subdirectories (`Listing` is returned then).
+/// Subdirectories are listed only on the first page
 #[utoipa::path(
     get,
     path = "/api/{bucket}",
     responses(
-        (status = 200, description = "Get all bucket's files successfully", body = [File]),
-        (status = 404, description = "Bucket not found", body = [File])
+        (status = 200, description = "Get bucket's files successfully. Listing is returned if delimiter is set", body = [File],
+            headers(("x-continuation-token" = String, description = "Token to get the next page"))),
+        (status = 400, description = "Invalid continuation token or zero limit", body = String),
+        (status = 404, description = "Bucket not found", body = [File]),
+        (status = 500, description = "Server error", body = String)
     ),
     tag = "buckets",
     params(
-        ("bucket" = String, Path, description = "Bucket id")
+        ("bucket" = String, Path, description = "Bucket id"),
+        FilesParams
     ),
 )]
-pub async fn get_files(
+pub async fn get_files<S: Backend>(
     Path(bucket): Path<String>,
-    State(db): State<Arc<Mutex<Sqlite>>>,
-) -> Result<impl IntoResponse, String> {
-    let mut repository = db.lock().await;
-    let result = repository.get_files(&bucket).unwrap_or_default();
-    let status = if result.is_empty() {
+    State(db): State<AsyncStorage<S>>,
+    Query(params): Query<FilesParams>,
+) -> Response {
+    // Empty page tells nothing about whether bucket exists or where the next page starts
+    if params.limit == Some(0) {
+        return (StatusCode::BAD_REQUEST, "limit must be positive").into_response();
+    }
+    let sort = params.sort.unwrap_or_default();
+    let after = match params.cursor.as_deref().map(Cursor::parse) {
+        Some(Some(cursor)) if cursor.sort == sort => Some(cursor),
+        Some(_) => {
+            return (StatusCode::BAD_REQUEST, "invalid continuation token").into_response();
+        }
+        None => None,
+    };
+    let first_page = after.is_none();
+    let delimiter = params.delimiter.clone().filter(|d| !d.is_empty());
+    let prefix = params.prefix.clone().unwrap_or_default();
+    // One more file is read to know whether there is the next page
+    let query = FileQuery {
+        modified_since: params.modified_since,
+        prefix: params.prefix.clone(),
+        glob: params.glob.clone(),
+        min_size: params.min_size,
+        max_size: params.max_size,
+        delimiter: delimiter.clone(),
+        sort,
+        after,
+        limit: params.limit.map(|l| l.saturating_add(1)),
+    };
+    let listed = db
+        .read(move |s| {
+            let files = s.get_files(&bucket, &query)?;
+            let prefixes = match delimiter {
+                Some(delimiter) if first_page => {
+                    s.get_common_prefixes(&bucket, &prefix, &delimiter)?
+                }
+                _ => vec![],
+            };
+            Ok((files, prefixes))
+        })
+        .await;
+    let (mut result, common_prefixes) = match listed {
+        Ok(listed) => listed,
+        Err(e) => {
+            tracing::error!("{e}");
+            return internal_server_error(&e).into_response();
+        }
+    };
+
+    let next = match params.limit {
+        Some(limit) if result.len() > limit => {
+            result.truncate(limit);
+            result.last().map(|f| Cursor::after(sort, f).token())
+        }
+        _ => None,
+    };
+    // Nothing matching filter isn't an error
+    let status = if result.is_empty() && common_prefixes.is_empty() && !params.is_filtered() {
         StatusCode::NOT_FOUND
     } else {
         StatusCode::OK
     };
-    Ok((status, Json(result)))
+    let body = if params.delimiter.is_some() {
+        Json(Listing {
+            common_prefixes,
+            files: result,
+        })
+        .into_response()
+    } else {
+        Json(result).into_response()
+    };
+    match next {
+        Some(token) => (status, [(CONTINUATION_TOKEN, token)], body).into_response(),
+        None => (status, body).into_response(),
+    }
+}
+
+/// Gets storage usage and deduplication statistics
+#[utoipa::path(
+    get,
+    path = "/api/stats",
+    responses(
+        (status = 200, description = "Statistics got successfully", body = Stats),
+        (status = 500, description = "Server error", body = String)
+    ),
+    tag = "buckets",
+    params(StatsParams),
+)]
+pub async fn get_stats<S: Backend>(
+    State(db): State<AsyncStorage<S>>,
+    Query(params): Query<StatsParams>,
+) -> Response {
+    let largest = params.largest.unwrap_or(LARGEST_FILES);
+    match db.read(move |s| s.get_stats(None, largest)).await {
+        Ok(stats) => Json(stats).into_response(),
+        Err(e) => internal_server_error(&e).into_response(),
+    }
+}
+
+/// Gets bucket's usage and deduplication statistics
+#[utoipa::path(
+    get,
+    path = "/api/bucket/{bucket}/stats",
+    responses(
+        (status = 200, description = "Statistics got successfully", body = Stats),
+        (status = 404, description = "Bucket not found", body = Stats),
+        (status = 500, description = "Server error", body = String)
+    ),
+    tag = "buckets",
+    params(
+        ("bucket" = String, Path, description = "Bucket id"),
+        StatsParams
+    ),
+)]
+pub async fn get_bucket_stats<S: Backend>(
+    Path(bucket): Path<String>,
+    State(db): State<AsyncStorage<S>>,
+    Query(params): Query<StatsParams>,
+) -> Response {
+    let largest = params.largest.unwrap_or(LARGEST_FILES);
+    match db.read(move |s| s.get_stats(Some(&bucket), largest)).await {
+        Ok(stats) => {
+            let status = if stats.files_count == 0 {
+                StatusCode::NOT_FOUND
+            } else {
+                StatusCode::OK
+            };
+            (status, Json(stats)).into_response()
+        }
+        Err(e) => internal_server_error(&e).into_response(),
+    }
 }
 
 /// Gets last inserted file info a bucket
 #[utoipa::path(
     get,
-    path = "/api/{bucket}/last",
+    path = "/api/bucket/{bucket}/last",
     responses(
         (status = 200, description = "Last file got successfully", body = File),
         (status = 404, description = "Bucket not found", body = String)
@@ -278,12 +882,11 @@ pub async fn get_files(
         ("bucket" = String, Path, description = "Bucket id")
     ),
 )]
-pub async fn get_last_file(
+pub async fn get_last_file<S: Backend>(
     Path(bucket): Path<String>,
-    State(db): State<Arc<Mutex<Sqlite>>>,
+    State(db): State<AsyncStorage<S>>,
 ) -> impl IntoResponse {
-    let mut repository = db.lock().await;
-    let result = match repository.get_last_file(&bucket) {
+    let result = match db.read(move |s| s.get_last_file(&bucket)).await {
         Ok(file) => Ok(Json(file)),
         Err(e) => Err(e.to_string()),
     };
@@ -296,35 +899,41 @@ pub async fn get_last_file(
     path = "/api/file/{id}",
     responses(
         (status = 200, response = FileReply),
-        (status = 404, description = "File not found", body = String)
+        (status = 206, response = FileReply),
+        (status = 304, description = "File content matches If-None-Match entity tag or not modified since If-Modified-Since"),
+        (status = 404, description = "File not found", body = String),
+        (status = 412, description = "File content doesn't match If-Match entity tag"),
+        (status = 416, description = "Requested range lies outside file content")
     ),
     tag = "files",
     params(
-        ("id" = i64, Path, description = "File id")
+        ("id" = i64, Path, description = "File id"),
+        ContentParams,
+        ("Range" = Option<String>, Header, description = "Single bytes range e.g. bytes=0-499"),
+        ("If-Range" = Option<String>, Header, description = "Entity tag the range is applied for"),
+        ("If-Match" = Option<String>, Header, description = "Entity tags one of which file must match"),
+        ("If-None-Match" = Option<String>, Header, description = "Entity tags none of which file must match"),
+        ("If-Modified-Since" = Option<String>, Header, description = "HTTP date. Ignored if If-None-Match is specified")
     ),
 )]
-pub async fn get_file_content(
+pub async fn get_file_content<S: Backend>(
     Path(id): Path<i64>,
-    State(db): State<Arc<Mutex<Sqlite>>>,
-) -> impl IntoResponse {
-    let mut repository = db.lock().await;
-    let info = match repository.get_file_info(id) {
+    State(db): State<AsyncStorage<S>>,
+    Query(params): Query<ContentParams>,
+    headers: HeaderMap,
+) -> Response {
+    let info = match db.read(move |s| s.get_file_info(id)).await {
         Ok(f) => f,
-        Err(e) => return (StatusCode::NOT_FOUND, e.to_string().into_response()),
-    };
-
-    let mut rdr = match repository.get_file_data(id) {
-        Ok(r) => r,
-        Err(e) => return (StatusCode::NOT_FOUND, e.to_string().into_response()),
+        Err(e) => return (StatusCode::NOT_FOUND, e.to_string()).into_response(),
     };
+    tracing::info!("File size {}", info.size);
 
-    // NOTE: Find way to pass raw Read to stream
-    let mut content = Vec::<u8>::with_capacity(info.size);
-    let size = rdr.read_to_end(&mut content).unwrap_or_default();
-    tracing::info!("File size {}", size);
-
-    let result = Ok(FileReply::new(content, info));
-    make_response(result)
+    if let Some(reply) = check_preconditions(&headers, &info) {
+        return reply;
+    }
+    file_reply(db, info, &headers)
+        .with_disposition(params.disposition.unwrap_or_default())
+        .into_response()
 }
 
 /// Gets file's information by file id
@@ -333,67 +942,205 @@ pub async fn get_file_content(
     path = "/api/file/{id}/meta",
     responses(
         (status = 200, body = File),
-        (status = 404, description = "File not found", body = String)
+        (status = 304, description = "File content matches If-None-Match entity tag or not modified since If-Modified-Since"),
+        (status = 404, description = "File not found", body = String),
+        (status = 412, description = "File content doesn't match If-Match entity tag")
     ),
     tag = "files",
     params(
-        ("id" = i64, Path, description = "File id")
+        ("id" = i64, Path, description = "File id"),
+        ("If-Match" = Option<String>, Header, description = "Entity tags one of which file must match"),
+        ("If-None-Match" = Option<String>, Header, description = "Entity tags none of which file must match"),
+        ("If-Modified-Since" = Option<String>, Header, description = "HTTP date. Ignored if If-None-Match is specified")
     ),
 )]
-pub async fn get_file_info(
+pub async fn get_file_info<S: Backend>(
     Path(id): Path<i64>,
-    State(db): State<Arc<Mutex<Sqlite>>>,
+    State(db): State<AsyncStorage<S>>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
-    let mut repository = db.lock().await;
-    let info = match repository.get_file_info(id) {
+    let info = match db.read(move |s| s.get_file_info(id)).await {
         Ok(f) => f,
         Err(e) => return Err(e.to_string()),
     };
+    Ok(file_info_reply(&headers, info))
+}
 
-    Ok(make_response(Ok(Json(info))))
+/// Gets file's information by bucket id and file path inside bucket
+#[utoipa::path(
+    get,
+    path = "/api/meta/{bucket}/{path}",
+    responses(
+        (status = 200, body = File),
+        (status = 304, description = "File content matches If-None-Match entity tag or not modified since If-Modified-Since"),
+        (status = 404, description = "File not found", body = String),
+        (status = 412, description = "File content doesn't match If-Match entity tag")
+    ),
+    tag = "files",
+    params(
+        ("bucket" = String, Path, description = "Bucket id"),
+        ("path" = String, Path, description = "File path inside bucket. May contain slashes"),
+        ("If-Match" = Option<String>, Header, description = "Entity tags one of which file must match"),
+        ("If-None-Match" = Option<String>, Header, description = "Entity tags none of which file must match"),
+        ("If-Modified-Since" = Option<String>, Header, description = "HTTP date. Ignored if If-None-Match is specified")
+    ),
+)]
+pub async fn search_file_info<S: Backend>(
+    Path((bucket, file_name)): Path<(String, String)>,
+    State(db): State<AsyncStorage<S>>,
+    headers: HeaderMap,
+) -> Response {
+    match db
+        .read(move |s| s.search_file_info(&bucket, &file_name))
+        .await
+    {
+        Ok(info) => file_info_reply(&headers, info),
+        Err(e) => (StatusCode::NOT_FOUND, e.to_string()).into_response(),
+    }
+}
+
+/// Replies with file's information and its validators unless preconditions failed
+fn file_info_reply(headers: &HeaderMap, info: File) -> Response {
+    if let Some(reply) = check_preconditions(headers, &info) {
+        return reply;
+    }
+    let etag = conditional::etag(&info.blake3_hash);
+    let last_modified = conditional::last_modified(info.updated_at);
+    (
+        [(header::ETAG, etag), (header::LAST_MODIFIED, last_modified)],
+        make_response(Ok(Json(info))),
+    )
+        .into_response()
 }
 
 /// Gets file binary content by bucket id and file path inside bucket
 #[utoipa::path(
     get,
-    path = "/api/{bucket}/{file_name}",
+    path = "/api/{bucket}/{path}",
     responses(
         (status = 200, response = FileReply),
-        (status = 404, description = "File not found", body = String)
+        (status = 206, response = FileReply),
+        (status = 304, description = "File content matches If-None-Match entity tag or not modified since If-Modified-Since"),
+        (status = 404, description = "File not found", body = String),
+        (status = 412, description = "File content doesn't match If-Match entity tag"),
+        (status = 416, description = "Requested range lies outside file content")
     ),
     tag = "files",
     params(
         ("bucket" = String, Path, description = "Bucket id"),
-        ("file_name" = String, Path, description = "File path inside bucket")
+        ("path" = String, Path, description = "File path inside bucket. May contain slashes"),
+        ContentParams,
+        ("Range" = Option<String>, Header, description = "Single bytes range e.g. bytes=0-499"),
+        ("If-Range" = Option<String>, Header, description = "Entity tag the range is applied for"),
+        ("If-Match" = Option<String>, Header, description = "Entity tags one of which file must match"),
+        ("If-None-Match" = Option<String>, Header, description = "Entity tags none of which file must match"),
+        ("If-Modified-Since" = Option<String>, Header, description = "HTTP date. Ignored if If-None-Match is specified")
     ),
 )]
-pub async fn search_and_get_file_content(
+pub async fn search_and_get_file_content<S: Backend>(
     Path((bucket, file_name)): Path<(String, String)>,
-    State(db): State<Arc<Mutex<Sqlite>>>,
-) -> impl IntoResponse {
-    let mut repository = db.lock().await;
-    let info = match repository.search_file_info(&bucket, &file_name) {
+    State(db): State<AsyncStorage<S>>,
+    Query(params): Query<ContentParams>,
+    headers: HeaderMap,
+) -> Response {
+    let info = match db
+        .read(move |s| s.search_file_info(&bucket, &file_name))
+        .await
+    {
         Ok(f) => f,
-        Err(e) => return (StatusCode::NOT_FOUND, e.to_string().into_response()),
+        Err(e) => return (StatusCode::NOT_FOUND, e.to_string()).into_response(),
     };
+    tracing::info!("File size {}", info.size);
 
-    let mut rdr = match repository.get_file_data(info.id) {
-        Ok(r) => r,
-        Err(e) => return (StatusCode::NOT_FOUND, e.to_string().into_response()),
+    if let Some(reply) = check_preconditions(&headers, &info) {
+        return reply;
+    }
+    file_reply(db, info, &headers)
+        .with_disposition(params.disposition.unwrap_or_default())
+        .into_response()
+}
+
+/// Downloads content stored under the hash specified no matter which files reference it.
+/// `HEAD` request can be used to check whether the content is stored
+#[utoipa::path(
+    get,
+    path = "/api/blob/{blake3_hash}",
+    responses(
+        (status = 200, response = FileReply),
+        (status = 206, response = FileReply),
+        (status = 304, description = "Content matches If-None-Match entity tag or not modified since If-Modified-Since"),
+        (status = 404, description = "Content not found", body = String),
+        (status = 412, description = "Content doesn't match If-Match entity tag"),
+        (status = 416, description = "Requested range lies outside content"),
+        (status = 500, description = "Server error", body = String)
+    ),
+    tag = "blobs",
+    params(
+        ("blake3_hash" = String, Path, description = "BLAKE3 hash of the content"),
+        ContentParams,
+        ("Range" = Option<String>, Header, description = "Single bytes range e.g. bytes=0-499"),
+        ("If-Range" = Option<String>, Header, description = "Entity tag the range is applied for"),
+        ("If-Match" = Option<String>, Header, description = "Entity tags one of which content must match"),
+        ("If-None-Match" = Option<String>, Header, description = "Entity tags none of which content must match"),
+        ("If-Modified-Since" = Option<String>, Header, description = "HTTP date. Ignored if If-None-Match is specified")
+    ),
+)]
+pub async fn get_blob_content<S: Backend>(
+    Path(blake3_hash): Path<String>,
+    State(db): State<AsyncStorage<S>>,
+    Query(params): Query<ContentParams>,
+    headers: HeaderMap,
+) -> Response {
+    let blob = match db.read(move |s| s.get_blob(&blake3_hash)).await {
+        Ok(b) => b,
+        Err(e) => return storage_error(&e).into_response(),
     };
 
-    // NOTE: Find way to pass raw Read to stream
-    let mut content = Vec::<u8>::with_capacity(info.size);
-    let size = rdr.read_to_end(&mut content).unwrap_or_default();
-    tracing::info!("File size {}", size);
+    let info = blob_file(blob);
+    if let Some(reply) = check_preconditions(&headers, &info) {
+        return reply;
+    }
+    file_reply(db, info, &headers)
+        .with_disposition(params.disposition.unwrap_or_default())
+        .into_response()
+}
 
-    make_response(Ok(FileReply::new(content, info)))
+/// Lists files in all buckets which content is stored under the hash specified
+#[utoipa::path(
+    get,
+    path = "/api/blob/{blake3_hash}/files",
+    responses(
+        (status = 200, description = "Files referencing the content got successfully", body = [File]),
+        (status = 404, description = "No files reference the content", body = [File]),
+        (status = 500, description = "Server error", body = String)
+    ),
+    tag = "blobs",
+    params(
+        ("blake3_hash" = String, Path, description = "BLAKE3 hash of the content")
+    ),
+)]
+pub async fn get_blob_files<S: Backend>(
+    Path(blake3_hash): Path<String>,
+    State(db): State<AsyncStorage<S>>,
+) -> Response {
+    let result = match db.read(move |s| s.get_blob_files(&blake3_hash)).await {
+        Ok(files) => files,
+        Err(e) => {
+            tracing::error!("{e}");
+            return internal_server_error(&e).into_response();
+        }
+    };
+    let status = if result.is_empty() {
+        StatusCode::NOT_FOUND
+    } else {
+        StatusCode::OK
+    };
+    (status, Json(result)).into_response()
 }
 
 macro_rules! delete_file {
-    ($repository:ident, $id:expr) => {{
-        let delete_result = $repository.delete_file($id);
-        let result = match delete_result {
+    ($delete_result:expr, $id:expr) => {{
+        let result = match $delete_result {
             Ok(deleted) => {
                 if deleted.files > 0 {
                     tracing::info!("file: {} deleted", $id);
@@ -431,18 +1178,17 @@ macro_rules! delete_file {
         ("id" = i64, Path, description = "File id")
     ),
 )]
-pub async fn delete_file(
+pub async fn delete_file<S: Backend>(
     Path(id): Path<i64>,
-    State(db): State<Arc<Mutex<Sqlite>>>,
+    State(db): State<AsyncStorage<S>>,
 ) -> Result<impl IntoResponse, String> {
-    let mut repository = db.lock().await;
-    delete_file!(repository, id)
+    delete_file!(db.write(move |s| s.delete_file(id)).await, id)
 }
 
 /// Deletes file by bucket id and file path inside bucket
 #[utoipa::path(
     delete,
-    path = "/api/{bucket}/{file_name}",
+    path = "/api/{bucket}/{path}",
     responses(
         (status = 200, description = "File successfully deleted", body = DeleteResult),
         (status = 404, description = "File not found", body = DeleteResult)
@@ -450,20 +1196,416 @@ pub async fn delete_file(
     tag = "files",
     params(
         ("bucket" = String, Path, description = "Bucket id"),
-        ("file_name" = String, Path, description = "File path inside bucket")
+        ("path" = String, Path, description = "File path inside bucket. May contain slashes")
     ),
 )]
-pub async fn search_and_delete_file(
+pub async fn search_and_delete_file<S: Backend>(
     Path((bucket, file_name)): Path<(String, String)>,
-    State(db): State<Arc<Mutex<Sqlite>>>,
+    State(db): State<AsyncStorage<S>>,
 ) -> Result<impl IntoResponse, String> {
-    let mut repository = db.lock().await;
-    match repository.search_file_info(&bucket, &file_name) {
-        Ok(f) => delete_file!(repository, f.id),
+    match db
+        .read(move |s| s.search_file_info(&bucket, &file_name))
+        .await
+    {
+        Ok(f) => {
+            let id = f.id;
+            // File is deleted by unique id so a file added under the same path in between is kept
+            delete_file!(db.write(move |s| s.delete_file(id)).await, id)
+        }
         Err(_e) => Ok((StatusCode::NOT_FOUND, Json(DeleteResult::default()))),
     }
 }
 
+/// Gets bucket's versioning configuration
+#[utoipa::path(
+    get,
+    path = "/api/bucket/{bucket}/versioning",
+    responses(
+        (status = 200, description = "Versioning configuration got successfully", body = Versioning),
+        (status = 500, description = "Server error", body = String)
+    ),
+    tag = "versions",
+    params(
+        ("bucket" = String, Path, description = "Bucket id")
+    ),
+)]
+pub async fn get_versioning<S: Backend>(
+    Path(bucket): Path<String>,
+    State(db): State<AsyncStorage<S>>,
+) -> Response {
+    match db.read(move |s| s.get_versioning(&bucket)).await {
+        Ok(enabled) => Json(Versioning { enabled }).into_response(),
+        Err(e) => internal_server_error(&e).into_response(),
+    }
+}
+
+/// Turns bucket's versioning on or off. Versions already kept aren't affected
+#[utoipa::path(
+    put,
+    path = "/api/bucket/{bucket}/versioning",
+    request_body = Versioning,
+    responses(
+        (status = 200, description = "Versioning configured successfully", body = Versioning),
+        (status = 500, description = "Server error", body = String)
+    ),
+    tag = "versions",
+    params(
+        ("bucket" = String, Path, description = "Bucket id")
+    ),
+)]
+pub async fn set_versioning<S: Backend>(
+    Path(bucket): Path<String>,
+    State(db): State<AsyncStorage<S>>,
+    Json(versioning): Json<Versioning>,
+) -> Response {
+    let enabled = versioning.enabled;
+    tracing::info!("bucket: {bucket} versioning: {enabled}");
+    match db.write(move |s| s.set_versioning(&bucket, enabled)).await {
+        Ok(()) => Json(versioning).into_response(),
+        Err(e) => {
+            tracing::error!("versioning not configured. Error: {e}");
+            internal_server_error(&e).into_response()
+        }
+    }
+}
+
+/// Renames file or moves it into another bucket. Only metadata is changed so content isn't copied
+#[utoipa::path(
+    post,
+    path = "/api/file/{id}/move",
+    request_body = Destination,
+    responses(
+        (status = 200, description = "File moved successfully", body = File),
+        (status = 400, description = "Bucket name is reserved or file path is empty", body = String),
+        (status = 404, description = "File not found", body = String),
+        (status = 409, description = "Another file already exists at the destination", body = String),
+        (status = 500, description = "Server error", body = String)
+    ),
+    tag = "files",
+    params(
+        ("id" = i64, Path, description = "File database id")
+    ),
+)]
+pub async fn move_file<S: Backend>(
+    Path(id): Path<i64>,
+    State(db): State<AsyncStorage<S>>,
+    Json(destination): Json<Destination>,
+) -> Response {
+    if let Some(rejected) = empty_path(&destination.path) {
+        return rejected.into_response();
+    }
+    let bucket = match destination.bucket {
+        Some(bucket) => {
+            if let Some(rejected) = reserved_bucket(&bucket) {
+                return rejected.into_response();
+            }
+            bucket
+        }
+        None => match db.read(move |s| s.get_file_info(id)).await {
+            Ok(info) => info.bucket,
+            Err(e) => return storage_error(&e).into_response(),
+        },
+    };
+    let path = destination.path;
+    let target = format!("{bucket}/{path}");
+    match db.write(move |s| s.move_file(id, &bucket, &path)).await {
+        Ok(Some(moved)) => {
+            tracing::info!("file: {id} moved to {target}");
+            Json(moved).into_response()
+        }
+        Ok(None) => (
+            StatusCode::CONFLICT,
+            format!("file {target} already exists"),
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("file {id} not moved. Error: {e}");
+            storage_error(&e).into_response()
+        }
+    }
+}
+
+/// Copies file within its bucket or into another one. Copy references the same content
+/// so that content is neither sent again nor stored twice
+#[utoipa::path(
+    post,
+    path = "/api/file/{id}/copy",
+    request_body = FileCopy,
+    responses(
+        (status = 201, description = "File copied successfully", body = [i64]),
+        (status = 400, description = "Bucket name is reserved, file path is empty or it's the file itself", body = String),
+        (status = 404, description = "File not found", body = String),
+        (status = 409, description = "Another file already exists at the destination", body = String),
+        (status = 500, description = "Server error", body = String)
+    ),
+    tag = "files",
+    params(
+        ("id" = i64, Path, description = "File database id")
+    ),
+)]
+pub async fn copy_file<S: Backend>(
+    Path(id): Path<i64>,
+    State(db): State<AsyncStorage<S>>,
+    Json(copy): Json<FileCopy>,
+) -> Response {
+    if let Some(rejected) = empty_path(&copy.path) {
+        return rejected.into_response();
+    }
+    if let Some(rejected) = copy.bucket.as_deref().and_then(reserved_bucket) {
+        return rejected.into_response();
+    }
+    let source = match db.read(move |s| s.get_file_info(id)).await {
+        Ok(info) => info,
+        Err(e) => return storage_error(&e).into_response(),
+    };
+    let bucket = copy.bucket.unwrap_or(source.bucket.clone());
+    if bucket == source.bucket && copy.path == source.path {
+        return (StatusCode::BAD_REQUEST, "file cannot be copied into itself").into_response();
+    }
+    let path = copy.path;
+    let overwrite = copy.overwrite;
+    let target = format!("{bucket}/{path}");
+    match db
+        .write(move |s| s.copy_file(id, &bucket, &path, overwrite))
+        .await
+    {
+        Ok(Some(copied)) => {
+            tracing::info!("file: {id} copied to {target} as {copied}");
+            created(Json(vec![copied])).into_response()
+        }
+        Ok(None) => (
+            StatusCode::CONFLICT,
+            format!("file {target} already exists"),
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("file {id} not copied. Error: {e}");
+            storage_error(&e).into_response()
+        }
+    }
+}
+
+/// Copies bucket's files into another bucket under the same paths. Copies reference
+/// the same content so that nothing is stored twice. Files already existing
+/// in target bucket are skipped unless overwrite is requested
+#[utoipa::path(
+    post,
+    path = "/api/bucket/{bucket}/copy",
+    request_body = BucketCopy,
+    responses(
+        (status = 201, description = "Files copied. Ids of the files written are returned", body = [i64]),
+        (status = 400, description = "Target bucket is the bucket itself or its name is reserved", body = String),
+        (status = 500, description = "Server error", body = String)
+    ),
+    tag = "buckets",
+    params(
+        ("bucket" = String, Path, description = "Bucket id")
+    ),
+)]
+pub async fn copy_bucket<S: Backend>(
+    Path(bucket): Path<String>,
+    State(db): State<AsyncStorage<S>>,
+    Json(copy): Json<BucketCopy>,
+) -> Response {
+    if copy.bucket == bucket {
+        return (
+            StatusCode::BAD_REQUEST,
+            "bucket cannot be copied into itself",
+        )
+            .into_response();
+    }
+    if let Some(rejected) = reserved_bucket(&copy.bucket) {
+        return rejected.into_response();
+    }
+    let source = bucket.clone();
+    let target = copy.bucket.clone();
+    let result = db
+        .write(move |s| s.copy_bucket(&source, &target, copy.prefix.as_deref(), copy.overwrite))
+        .await;
+    match result {
+        Ok(copied) => {
+            tracing::info!("bucket: {bucket} files copied: {}", copied.len());
+            created(Json(copied)).into_response()
+        }
+        Err(e) => {
+            tracing::error!("bucket {bucket} not copied. Error: {e}");
+            internal_server_error(&e).into_response()
+        }
+    }
+}
+
+/// Lists file's versions starting from the current one
+#[utoipa::path(
+    get,
+    path = "/api/file/{id}/versions",
+    responses(
+        (status = 200, description = "File versions got successfully", body = [FileVersion]),
+        (status = 404, description = "File not found", body = String),
+        (status = 500, description = "Server error", body = String)
+    ),
+    tag = "versions",
+    params(
+        ("id" = i64, Path, description = "File database id")
+    ),
+)]
+pub async fn get_file_versions<S: Backend>(
+    Path(id): Path<i64>,
+    State(db): State<AsyncStorage<S>>,
+) -> Response {
+    match db.read(move |s| s.get_file_versions(id)).await {
+        Ok(versions) if !versions.is_empty() => Json(versions).into_response(),
+        Ok(_) => (StatusCode::NOT_FOUND, format!("file {id} not found")).into_response(),
+        Err(e) => storage_error(&e).into_response(),
+    }
+}
+
+/// Downloads file version's content
+#[utoipa::path(
+    get,
+    path = "/api/file/{id}/versions/{version}",
+    responses(
+        (status = 200, response = FileReply),
+        (status = 206, response = FileReply),
+        (status = 304, description = "Version content matches If-None-Match entity tag or not modified since If-Modified-Since"),
+        (status = 404, description = "File or version not found", body = String),
+        (status = 412, description = "Version content doesn't match If-Match entity tag"),
+        (status = 416, description = "Requested range lies outside version content"),
+        (status = 500, description = "Server error", body = String)
+    ),
+    tag = "versions",
+    params(
+        ("id" = i64, Path, description = "File database id"),
+        ("version" = i64, Path, description = "File version"),
+        ContentParams,
+        ("Range" = Option<String>, Header, description = "Single bytes range e.g. bytes=0-499"),
+        ("If-Range" = Option<String>, Header, description = "Entity tag the range is applied for"),
+        ("If-Match" = Option<String>, Header, description = "Entity tags one of which version must match"),
+        ("If-None-Match" = Option<String>, Header, description = "Entity tags none of which version must match"),
+        ("If-Modified-Since" = Option<String>, Header, description = "HTTP date. Ignored if If-None-Match is specified")
+    ),
+)]
+pub async fn get_file_version_content<S: Backend>(
+    Path((id, version)): Path<(i64, i64)>,
+    State(db): State<AsyncStorage<S>>,
+    Query(params): Query<ContentParams>,
+    headers: HeaderMap,
+) -> Response {
+    let found = db
+        .read(move |s| {
+            let info = s.get_file_info(id)?;
+            let versions = s.get_file_versions(id)?;
+            Ok((info, versions))
+        })
+        .await;
+    let (info, versions) = match found {
+        Ok(found) => found,
+        Err(e) => return storage_error(&e).into_response(),
+    };
+    let Some(version) = versions.into_iter().find(|v| v.version == version) else {
+        return (
+            StatusCode::NOT_FOUND,
+            format!("version {version} not found"),
+        )
+            .into_response();
+    };
+
+    // Version's content is served as file's one
+    let info = File {
+        blake3_hash: version.blake3_hash,
+        size: version.size,
+        content_type: version.content_type,
+        updated_at: version.created_at,
+        ..info
+    };
+    if let Some(reply) = check_preconditions(&headers, &info) {
+        return reply;
+    }
+    file_reply(db, info, &headers)
+        .with_disposition(params.disposition.unwrap_or_default())
+        .into_response()
+}
+
+/// Deletes file's version. Previous version becomes current if the current one is deleted
+/// and the file itself is deleted together with its only version
+#[utoipa::path(
+    delete,
+    path = "/api/file/{id}/versions/{version}",
+    responses(
+        (status = 200, description = "Version successfully deleted", body = DeleteResult),
+        (status = 404, description = "File or version not found", body = DeleteResult),
+        (status = 500, description = "Server error", body = String)
+    ),
+    tag = "versions",
+    params(
+        ("id" = i64, Path, description = "File database id"),
+        ("version" = i64, Path, description = "File version")
+    ),
+)]
+pub async fn delete_file_version<S: Backend>(
+    Path((id, version)): Path<(i64, i64)>,
+    State(db): State<AsyncStorage<S>>,
+) -> Response {
+    match db.write(move |s| s.delete_file_version(id, version)).await {
+        Ok(deleted) if deleted.files > 0 => {
+            tracing::info!("file: {id} version {version} deleted");
+            Json(deleted).into_response()
+        }
+        Ok(deleted) => (StatusCode::NOT_FOUND, Json(deleted)).into_response(),
+        Err(e) => {
+            tracing::error!("file: {id} version {version} not deleted. Error: {e}");
+            storage_error(&e).into_response()
+        }
+    }
+}
+
+/// Makes file version's content current. It's written as the new version
+/// so that the content replaced is kept as the previous one
+#[utoipa::path(
+    post,
+    path = "/api/file/{id}/versions/{version}/restore",
+    responses(
+        (status = 200, description = "Version successfully restored", body = FileVersion),
+        (status = 404, description = "File or version not found", body = String),
+        (status = 500, description = "Server error", body = String)
+    ),
+    tag = "versions",
+    params(
+        ("id" = i64, Path, description = "File database id"),
+        ("version" = i64, Path, description = "File version")
+    ),
+)]
+pub async fn restore_file_version<S: Backend>(
+    Path((id, version)): Path<(i64, i64)>,
+    State(db): State<AsyncStorage<S>>,
+) -> Response {
+    let found = db.read(move |s| s.get_file_versions(id)).await;
+    match found {
+        Ok(versions) if versions.iter().any(|v| v.version == version) => {}
+        Ok(_) => {
+            return (
+                StatusCode::NOT_FOUND,
+                format!("version {version} not found"),
+            )
+                .into_response();
+        }
+        Err(e) => return storage_error(&e).into_response(),
+    }
+
+    match db.write(move |s| s.restore_file_version(id, version)).await {
+        Ok(restored) => {
+            tracing::info!(
+                "file: {id} version {version} restored as {}",
+                restored.version
+            );
+            Json(restored).into_response()
+        }
+        Err(e) => {
+            tracing::error!("file: {id} version {version} not restored. Error: {e}");
+            internal_server_error(&e).into_response()
+        }
+    }
+}
+
 fn make_response(result: Result<impl IntoResponse + Sized, String>) -> (StatusCode, Response) {
     match result {
         Ok(response) => (StatusCode::OK, response.into_response()),
@@ -478,23 +1620,53 @@ fn log_file_operation_result<E: Display>(
     operation_result: Result<i64, E>,
     file_name: &str,
     read_bytes: u64,
-) -> Option<i64> {
-    match operation_result {
+) -> Result<i64, E> {
+    match &operation_result {
         Ok(id) => {
             tracing::info!("file: {} read: {} file id: {}", file_name, read_bytes, id);
-            Some(id)
         }
         Err(e) => {
             tracing::error!("file '{}' not inserted. Error: {}", file_name, e);
-            None
         }
     }
+    operation_result
+}
+
+/// Rejects writing into bucket which name is taken by API route
+fn reserved_bucket(bucket: &str) -> Option<(StatusCode, Response)> {
+    RESERVED_BUCKETS.contains(&bucket).then(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("bucket name {bucket} is reserved").into_response(),
+        )
+    })
+}
+
+/// Rejects file path without name because such file couldn't be reached
+fn empty_path(path: &str) -> Option<(StatusCode, Response)> {
+    path.trim().is_empty().then(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            "file path is empty".into_response(),
+        )
+    })
 }
 
 fn created<S: IntoResponse>(s: S) -> (StatusCode, Response) {
     (StatusCode::CREATED, s.into_response())
 }
 
+/// Responds with storage error. Requests for missing items or conflicting with
+/// existing ones are client's mistakes while anything else is server's failure
+fn storage_error(e: &io::Error) -> (StatusCode, Response) {
+    let status = match e.kind() {
+        io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+        io::ErrorKind::AlreadyExists => StatusCode::CONFLICT,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    (status, e.to_string().into_response())
+}
+
 fn internal_server_error<E: ToString>(e: &E) -> (StatusCode, Response) {
     (
         StatusCode::INTERNAL_SERVER_ERROR,
@@ -502,18 +1674,279 @@ fn internal_server_error<E: ToString>(e: &E) -> (StatusCode, Response) {
     )
 }
 
-async fn read_from_stream<S, E>(stream: S) -> io::Result<(Vec<u8>, usize)>
+/// Stages stream's content into storage. See `stage_upload`
+async fn stage_stream<S: Backend, St, E>(
+    db: &AsyncStorage<S>,
+    stream: St,
+) -> io::Result<(Upload, UploadGuard<S>)>
 where
-    S: Stream<Item = Result<Bytes, E>> + StreamExt,
+    St: Stream<Item = Result<Bytes, E>> + StreamExt,
     E: Sync + std::error::Error + Send + 'static,
 {
     // Convert the stream into an `AsyncRead`.
+    let body_with_io_error = stream.map_err(|err| io::Error::other(err));
+    stage_upload(db, StreamReader::new(body_with_io_error)).await
+}
+
+/// Stages reader's content into storage by chunks so as not to load it into memory.
+/// Storage is locked only while a chunk is being written so that uploads don't block each other.
+/// Staged content is removed on failure. Returned guard removes it too unless it's handed
+/// over to storage operation that either commits or aborts the upload
+async fn stage_upload<S: Backend, R: AsyncRead>(
+    db: &AsyncStorage<S>,
+    reader: R,
+) -> io::Result<(Upload, UploadGuard<S>)> {
+    let mut upload = db.write(|s| s.begin_upload()).await?;
+    let guard = UploadGuard::new(db, &upload);
+    futures::pin_mut!(reader);
+    let mut chunk = vec![0u8; WRITE_CHUNK_SIZE];
+    loop {
+        let read = match read_chunk(&mut reader, &mut chunk).await {
+            Ok(0) => return Ok((upload, guard)),
+            Ok(read) => read,
+            Err(e) => {
+                guard.disarm();
+                abort_upload(db, upload).await;
+                return Err(e);
+            }
+        };
+        (upload, chunk) = append_chunk(db, upload, chunk, read).await?;
+    }
+}
+
+/// Stages synchronous reader's content into storage the same way as `stage_upload` does.
+/// Must be run on blocking thread. Storage operations are awaited using the runtime specified
+fn stage_blocking<S: Backend, R: Read>(
+    runtime: &Handle,
+    db: &AsyncStorage<S>,
+    mut reader: R,
+) -> io::Result<(Upload, UploadGuard<S>)> {
+    let mut upload = runtime.block_on(db.write(|s| s.begin_upload()))?;
+    let guard = UploadGuard::new(db, &upload);
+    let mut chunk = vec![0u8; WRITE_CHUNK_SIZE];
+    loop {
+        let read = match read_chunk_blocking(&mut reader, &mut chunk) {
+            Ok(0) => return Ok((upload, guard)),
+            Ok(read) => read,
+            Err(e) => {
+                guard.disarm();
+                runtime.block_on(abort_upload(db, upload));
+                return Err(e);
+            }
+        };
+        (upload, chunk) = runtime.block_on(append_chunk(db, upload, chunk, read))?;
+    }
+}
+
+/// Appends the first `read` bytes of the chunk to staged upload. Upload is aborted on failure.
+/// Buffer is given back by blocking operation so as to be reused for the next chunk
+async fn append_chunk<S: Backend>(
+    db: &AsyncStorage<S>,
+    mut upload: Upload,
+    chunk: Vec<u8>,
+    read: usize,
+) -> io::Result<(Upload, Vec<u8>)> {
+    db.write(
+        move |s| match s.append_upload(&mut upload, &chunk[..read]) {
+            Ok(()) => Ok((upload, chunk)),
+            Err(e) => {
+                if let Err(abort_error) = s.abort_upload(upload) {
+                    tracing::error!("staged upload not removed. Error: {abort_error}");
+                }
+                Err(e)
+            }
+        },
+    )
+    .await
+}
+
+async fn abort_upload<S: Backend>(db: &AsyncStorage<S>, upload: Upload) {
+    if let Err(e) = db.write(move |s| s.abort_upload(upload)).await {
+        tracing::error!("staged upload not removed. Error: {e}");
+    }
+}
+
+/// Removes staged upload in background when it's dropped unless it's disarmed.
+/// Handler's future is dropped in the middle when client disconnects
+/// so staged content would be left in storage forever otherwise
+struct UploadGuard<S: Backend> {
+    db: AsyncStorage<S>,
+    upload_id: Option<i64>,
+}
+
+impl<S: Backend> UploadGuard<S> {
+    fn new(db: &AsyncStorage<S>, upload: &Upload) -> Self {
+        Self {
+            db: db.clone(),
+            upload_id: Some(upload.id()),
+        }
+    }
+
+    /// Must be called as soon as storage takes care of the upload i.e. commits or aborts it
+    fn disarm(mut self) {
+        self.upload_id = None;
+    }
+}
+
+impl<S: Backend> Drop for UploadGuard<S> {
+    fn drop(&mut self) {
+        let Some(id) = self.upload_id.take() else {
+            return;
+        };
+        let Ok(runtime) = Handle::try_current() else {
+            tracing::error!("abandoned upload {id} not removed. No runtime");
+            return;
+        };
+        let db = self.db.clone();
+        runtime.spawn(async move {
+            // Abort needs only upload's id
+            match db.write(move |s| s.abort_upload(Upload::new(id))).await {
+                Ok(()) => tracing::info!("abandoned upload {id} removed"),
+                Err(e) => tracing::error!("abandoned upload {id} not removed. Error: {e}"),
+            }
+        });
+    }
+}
+
+/// Fills the buffer as much as possible so as not to write tiny network frames as separate chunks.
+/// Returns less than buffer length only at the end of the stream
+async fn read_chunk<R: AsyncRead + Unpin>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let read = reader.read(&mut buf[filled..]).await?;
+        if read == 0 {
+            break;
+        }
+        filled += read;
+    }
+    Ok(filled)
+}
+
+/// Blocking counterpart of `read_chunk`
+fn read_chunk_blocking<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let read = match reader.read(&mut buf[filled..]) {
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        if read == 0 {
+            break;
+        }
+        filled += read;
+    }
+    Ok(filled)
+}
+
+async fn commit_upload<S: Backend>(
+    db: &AsyncStorage<S>,
+    (upload, guard): (Upload, UploadGuard<S>),
+    file_name: &str,
+    bucket: &str,
+    declared_type: Option<&str>,
+) -> io::Result<i64> {
+    let size = upload.size();
+    let content_type = content_type::resolve(declared_type, file_name, upload.head());
+    let path = file_name.to_owned();
+    let bucket = bucket.to_owned();
+    let insert_result = db
+        .write(move |s| {
+            guard.disarm();
+            s.commit_upload(upload, &path, &bucket, &content_type)
+        })
+        .await;
+    log_file_operation_result(insert_result, file_name, size)
+}
+
+/// Writes stream into anonymous temporary file so as not to keep it in memory
+async fn spool_stream<St, E>(stream: St) -> io::Result<std::fs::File>
+where
+    St: Stream<Item = Result<Bytes, E>> + StreamExt,
+    E: Sync + std::error::Error + Send + 'static,
+{
     let body_with_io_error = stream.map_err(|err| io::Error::other(err));
     let body_reader = StreamReader::new(body_with_io_error);
     futures::pin_mut!(body_reader);
-    let mut buffer = Vec::new();
 
-    let copied_bytes = tokio::io::copy(&mut body_reader, &mut buffer).await?;
-    let copied_bytes = usize::try_from(copied_bytes).unwrap_or(usize::MAX);
-    Ok((buffer, copied_bytes))
+    let mut file = tokio::fs::File::from_std(tempfile::tempfile()?);
+    tokio::io::copy(&mut body_reader, &mut file).await?;
+    let mut file = file.into_std().await;
+    file.rewind()?;
+    Ok(file)
+}
+
+/// Replies precondition failure status if request's validators don't allow to send the file
+fn check_preconditions(headers: &HeaderMap, info: &File) -> Option<Response> {
+    let etag = conditional::etag(&info.blake3_hash);
+    let last_modified = conditional::last_modified(info.updated_at);
+    conditional::evaluate(headers, &etag, info.updated_at).map(|status| {
+        (
+            status,
+            [(header::ETAG, etag), (header::LAST_MODIFIED, last_modified)],
+        )
+            .into_response()
+    })
+}
+
+/// Blob is served the same way as the file named by content hash
+fn blob_file(blob: Blob) -> File {
+    File {
+        id: 0,
+        path: blob.blake3_hash.clone(),
+        bucket: String::new(),
+        blake3_hash: blob.blake3_hash,
+        size: blob.size,
+        content_type: blob.content_type,
+        created_at: blob.created_at,
+        updated_at: blob.created_at,
+    }
+}
+
+/// Makes file content reply that contains only the part requested by `Range` header if any
+fn file_reply<S: Backend>(db: AsyncStorage<S>, info: File, headers: &HeaderMap) -> FileReply {
+    let size = info.size as u64;
+    let range = if conditional::range_applicable(headers, &conditional::etag(&info.blake3_hash)) {
+        headers.get(header::RANGE).and_then(|v| v.to_str().ok())
+    } else {
+        None
+    };
+    let range = ContentRange::parse(range, size);
+    let (start, end) = range.bounds(size);
+    let body = Body::from_stream(blob_stream(db, info.blake3_hash.clone(), start, end));
+    FileReply::new(body, info).with_range(range)
+}
+
+/// Streams blob content between start (inclusive) and end (exclusive) offsets by chunks.
+/// Reader is taken from the pool only while a chunk is being read
+/// so that other requests are not blocked until the whole blob is sent
+pub(crate) fn blob_stream<S: Backend>(
+    db: AsyncStorage<S>,
+    blake3_hash: String,
+    start: u64,
+    end: u64,
+) -> impl Stream<Item = io::Result<Bytes>> {
+    futures::stream::try_unfold(start, move |offset| {
+        let db = db.clone();
+        let blake3_hash = blake3_hash.clone();
+        async move {
+            if offset >= end {
+                return Ok(None);
+            }
+            let capacity = usize::try_from(READ_CHUNK_SIZE.min(end - offset)).unwrap_or_default();
+            let chunk = db
+                .read(move |s| {
+                    let mut chunk = vec![0u8; capacity];
+                    let read = s.get_file_data(&blake3_hash, offset, &mut chunk)?;
+                    chunk.truncate(read);
+                    Ok(chunk)
+                })
+                .await?;
+            if chunk.is_empty() {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+            }
+            let read = chunk.len() as u64;
+            Ok(Some((Bytes::from(chunk), offset + read)))
+        }
+    })
 }
@@ -4,7 +4,7 @@
 #![allow(clippy::missing_errors_doc)]
 #![allow(clippy::missing_panics_doc)]
 
-use std::{path::PathBuf, sync::Arc};
+use std::path::PathBuf;
 
 use axum::{
     Router,
@@ -12,23 +12,32 @@ use axum::{
     routing::post,
     routing::{delete, get},
 };
-use futures::lock::Mutex;
 use rusqlite::Error;
 use std::time::Duration;
 use tokio::signal;
 use tower::ServiceBuilder;
-use tower_http::{
-    classify::ServerErrorsFailureClass, limit::RequestBodyLimitLayer, trace::TraceLayer,
-};
+use tower_http::{classify::ServerErrorsFailureClass, trace::TraceLayer};
 use tracing::Span;
 
+mod async_storage;
+mod conditional;
+mod content_type;
 pub mod domain;
 pub mod file_reply;
+pub mod filesystem;
 mod handlers;
+pub mod listing;
+mod pool;
 pub mod sqlite;
+mod tar_import;
+mod zip_export;
 
-use crate::sqlite::{Mode, Sqlite};
-use crate::{domain::Storage, file_reply::FileReply};
+use crate::async_storage::AsyncStorage;
+use crate::domain::{Backend, Storage};
+use crate::file_reply::FileReply;
+use crate::filesystem::FileSystem;
+use crate::pool::ConnectionPool;
+use crate::sqlite::{Mode, SchemaVersion, Sqlite};
 use std::env;
 use std::net::SocketAddr;
 use std::path::Path;
@@ -40,8 +49,79 @@ use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 const DB_FILE: &str = "bstore.db";
 const CURRENT_DIR: &str = "./";
+const DEFAULT_READ_POOL_SIZE: usize = 8;
+const BLOBS_DIR: &str = "blobs";
+
+/// Where blob's content is kept
+enum BlobStore {
+    /// Inside `SQLite` database together with metadata
+    Sqlite,
+    /// In content addressed files under the directory specified
+    FileSystem(PathBuf),
+}
 
 pub async fn run() {
+    init_tracing();
+
+    // Configuration from environment
+    let read_pool_size = env::var("BSTORE_READ_POOL_SIZE")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_READ_POOL_SIZE);
+
+    // Start init
+    let db = database_path();
+    match prepare_database(&db) {
+        Ok(schema) => tracing::info!("database schema version: {}", schema.current),
+        Err(e) => {
+            tracing::error!("Database cannot be prepared. Error: {e}");
+            return;
+        }
+    }
+
+    if let Err(e) = check_no_reserved_buckets(&db) {
+        tracing::error!("Database cannot be served. Error: {e}");
+        return;
+    }
+
+    let store = blob_store();
+    match remove_pending_uploads(&db, &store) {
+        Ok(0) => {}
+        Ok(removed) => tracing::info!("interrupted uploads removed: {removed}"),
+        Err(e) => {
+            tracing::error!("Interrupted uploads cannot be removed. Error: {e}");
+            return;
+        }
+    }
+
+    let routes = match store {
+        BlobStore::Sqlite => {
+            if let Err(e) = check_no_external_blobs(&db) {
+                tracing::error!("SQLite blob store cannot be used. Error: {e}");
+                return;
+            }
+            create_routes(db, read_pool_size).map_err(|e| e.to_string())
+        }
+        BlobStore::FileSystem(root) => {
+            if let Err(e) = check_no_embedded_blobs(&db) {
+                tracing::error!("Filesystem blob store cannot be used. Error: {e}");
+                return;
+            }
+            tracing::info!("blobs are stored in {}", root.display());
+            create_filesystem_routes(db, root, read_pool_size).map_err(|e| e.to_string())
+        }
+    };
+    serve(routes).await;
+}
+
+/// Runs server that keeps everything in memory. All data is lost on shutdown
+pub async fn run_ephemeral() {
+    init_tracing();
+    tracing::info!("ephemeral server started. Data won't be persisted");
+    serve(create_ephemeral_routes().map_err(|e| e.to_string())).await;
+}
+
+fn init_tracing() {
     tracing_subscriber::registry()
         .with(tracing_subscriber::EnvFilter::new(
             std::env::var("RUST_LOG")
@@ -49,25 +129,14 @@ pub async fn run() {
         ))
         .with(tracing_subscriber::fmt::layer())
         .init();
+}
 
-    // Configuration from environment
-    let db_file = env::var("BSTORE_DATA_FILE").unwrap_or_else(|_| String::from(DB_FILE));
-    let dir = env::var("BSTORE_DATA_DIR").unwrap_or_else(|_| String::from(CURRENT_DIR));
+async fn serve(routes: Result<Router, String>) {
     let port = env::var("BSTORE_PORT").unwrap_or_else(|_| String::from("5000"));
-
-    // Start init
-    let db = Path::new(&dir).join(&db_file);
-    if !db.exists() {
-        Sqlite::open(db.clone(), Mode::ReadWrite)
-            .expect("Database file cannot be created")
-            .new_database()
-            .unwrap_or_default();
-    }
-
     let listen_socket = SocketAddr::from(([0, 0, 0, 0], port.parse().unwrap_or_default()));
     tracing::info!("listening on {listen_socket}");
 
-    match create_routes(db) {
+    match routes {
         Ok(app) => {
             if let Ok(listener) = tokio::net::TcpListener::bind(listen_socket).await {
                 if let Err(e) = axum::serve(listener, app)
@@ -80,8 +149,119 @@ pub async fn run() {
                 tracing::error!("Failed to start server at 0.0.0.0:{port}");
             }
         }
-        Err(err) => tracing::error!("Failed to start server. Error:{err:?}"),
+        Err(err) => tracing::error!("Failed to start server. Error: {err}"),
+    }
+}
+
+/// Creates configured database or applies pending schema migrations to existing one
+pub fn migrate() -> Result<SchemaVersion, Error> {
+    prepare_database(&database_path())
+}
+
+/// Gets configured database's schema version
+pub fn check_schema() -> Result<SchemaVersion, Error> {
+    Sqlite::open(database_path(), Mode::ReadOnly)?.schema_version()
+}
+
+fn database_path() -> PathBuf {
+    let db_file = env::var("BSTORE_DATA_FILE").unwrap_or_else(|_| String::from(DB_FILE));
+    let dir = env::var("BSTORE_DATA_DIR").unwrap_or_else(|_| String::from(CURRENT_DIR));
+    Path::new(&dir).join(db_file)
+}
+
+/// Blob store selected by `BSTORE_BLOB_STORE` that is either `sqlite` (default) or `filesystem`.
+/// Files are kept in `BSTORE_BLOB_DIR` or `blobs` subdirectory of data directory if it's not set.
+/// Content isn't moved when the store is switched so the store cannot be changed
+/// for database that already keeps any content. Server refuses to start otherwise
+fn blob_store() -> BlobStore {
+    match env::var("BSTORE_BLOB_STORE") {
+        Ok(store) if store.eq_ignore_ascii_case("filesystem") => {
+            let root = env::var("BSTORE_BLOB_DIR").map_or_else(
+                |_| {
+                    let dir =
+                        env::var("BSTORE_DATA_DIR").unwrap_or_else(|_| String::from(CURRENT_DIR));
+                    Path::new(&dir).join(BLOBS_DIR)
+                },
+                PathBuf::from,
+            );
+            BlobStore::FileSystem(root)
+        }
+        _ => BlobStore::Sqlite,
+    }
+}
+
+/// Fails if database keeps blob's content inside because filesystem store cannot read it
+fn check_no_embedded_blobs(db: &Path) -> Result<(), String> {
+    let embedded = Sqlite::open(db, Mode::ReadOnly)
+        .and_then(|storage| storage.has_embedded_blobs())
+        .map_err(|e| e.to_string())?;
+    if embedded {
+        Err(format!(
+            "database {} keeps blobs content inside. Use sqlite blob store for it",
+            db.display()
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Fails if blob's content is kept in files because `SQLite` store cannot read it
+fn check_no_external_blobs(db: &Path) -> Result<(), String> {
+    let external = Sqlite::open(db, Mode::ReadOnly)
+        .and_then(|storage| storage.has_external_blobs())
+        .map_err(|e| e.to_string())?;
+    if external {
+        Err(format!(
+            "database {} keeps blobs content in files. Use filesystem blob store for it",
+            db.display()
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Fails if database has buckets named after API routes. They were written before
+/// the routes appeared and their files cannot be reached anymore
+fn check_no_reserved_buckets(db: &Path) -> Result<(), String> {
+    let reserved = Sqlite::open(db, Mode::ReadOnly)
+        .and_then(|storage| storage.existing_buckets(&handlers::RESERVED_BUCKETS))
+        .map_err(|e| e.to_string())?;
+    if reserved.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "database {} has buckets {} named after API routes so their files cannot be reached. \
+             Rename them in file and versioned_bucket tables e.g. \
+             UPDATE file SET bucket = 'new name' WHERE bucket = '{}'",
+            db.display(),
+            reserved.join(", "),
+            reserved[0]
+        ))
+    }
+}
+
+/// Removes uploads that were in progress when server stopped. Nothing refers to them
+/// so they'd never be removed otherwise
+fn remove_pending_uploads(db: &Path, store: &BlobStore) -> Result<usize, String> {
+    match store {
+        BlobStore::Sqlite => Sqlite::open(db, Mode::ReadWrite)
+            .and_then(|mut storage| storage.remove_pending_uploads())
+            .map_err(|e| e.to_string()),
+        BlobStore::FileSystem(root) => FileSystem::open(db, Mode::ReadWrite, root)
+            .and_then(|mut storage| storage.remove_pending_uploads())
+            .map_err(|e| e.to_string()),
+    }
+}
+
+fn prepare_database(db: &Path) -> Result<SchemaVersion, Error> {
+    let exists = db.exists();
+    let storage = Sqlite::open(db, Mode::ReadWrite)?;
+    if exists {
+        storage.migrate()?;
+    } else {
+        storage.new_database()?;
     }
+    storage.schema_version()
 }
 
 #[derive(OpenApi)]
@@ -90,18 +270,36 @@ pub async fn run() {
             handlers::get_buckets,
             handlers::insert_many_from_form,
             handlers::insert_file,
+            handlers::put_file,
+            handlers::link_file,
             handlers::insert_zipped_bucket,
+            handlers::insert_tarred_bucket,
+            handlers::get_zipped_bucket,
             handlers::delete_file,
             handlers::delete_bucket,
             handlers::get_files,
             handlers::get_last_file,
+            handlers::get_stats,
+            handlers::get_bucket_stats,
             handlers::search_and_get_file_content,
             handlers::search_and_delete_file,
             handlers::get_file_content,
             handlers::get_file_info,
+            handlers::search_file_info,
+            handlers::move_file,
+            handlers::copy_file,
+            handlers::copy_bucket,
+            handlers::get_versioning,
+            handlers::set_versioning,
+            handlers::get_file_versions,
+            handlers::get_file_version_content,
+            handlers::delete_file_version,
+            handlers::restore_file_version,
+            handlers::get_blob_content,
+            handlers::get_blob_files,
         ),
         components(
-            schemas(kernel::Bucket, kernel::File, kernel::DeleteResult),
+            schemas(kernel::Bucket, kernel::File, kernel::DeleteResult, kernel::PutResult, kernel::Versioning, kernel::FileVersion, kernel::Blob, kernel::BlobLink, kernel::Stats, kernel::Listing, kernel::Destination, kernel::FileCopy, kernel::BucketCopy, file_reply::Disposition, listing::SortBy),
             responses(FileReply),
         ),
         tags(
@@ -110,35 +308,102 @@ pub async fn run() {
     )]
 struct ApiDoc;
 
-pub fn create_routes(db: PathBuf) -> Result<Router, Error> {
+/// Creates API routes over the database specified.
+/// GET requests are served by `read_pool_size` read-only connections concurrently
+pub fn create_routes(db: PathBuf, read_pool_size: usize) -> Result<Router, Error> {
+    let storage = ConnectionPool::open(db, read_pool_size)?;
+    Ok(routes(AsyncStorage::new(storage)))
+}
+
+/// Creates API routes over the database specified that keeps only metadata.
+/// Blob's content is kept in files under `blob_dir`
+pub fn create_filesystem_routes(
+    db: PathBuf,
+    blob_dir: PathBuf,
+    read_pool_size: usize,
+) -> Result<Router, filesystem::Error> {
+    let storage = ConnectionPool::open_with(read_pool_size, |mode| {
+        FileSystem::open(&db, mode, &blob_dir)
+    })?;
+    Ok(routes(AsyncStorage::new(storage)))
+}
+
+/// Creates API routes over new in-memory database. Every call makes isolated storage
+/// so it's handy for tests. Both reads and writes are served by the single connection
+/// because in-memory database cannot be shared between connections
+pub fn create_ephemeral_routes() -> Result<Router, Error> {
+    Ok(create_storage_routes(Sqlite::in_memory()?))
+}
+
+/// Creates API routes over pre-built storage. All requests are served by this single instance
+pub fn create_storage_routes<S: Backend>(storage: S) -> Router {
+    routes(AsyncStorage::new(ConnectionPool::single(storage)))
+}
+
+fn routes<S: Backend>(storage: AsyncStorage<S>) -> Router {
     let file_api = Router::new()
         .route(
             "/{id}",
             delete(handlers::delete_file).get(handlers::get_file_content),
         )
-        .route("/{id}/meta", get(handlers::get_file_info));
+        .route("/{id}/meta", get(handlers::get_file_info))
+        .route("/{id}/move", post(handlers::move_file))
+        .route("/{id}/copy", post(handlers::copy_file))
+        .route("/{id}/versions", get(handlers::get_file_versions))
+        .route(
+            "/{id}/versions/{version}",
+            get(handlers::get_file_version_content).delete(handlers::delete_file_version),
+        )
+        .route(
+            "/{id}/versions/{version}/restore",
+            post(handlers::restore_file_version),
+        );
+
+    let blob_api = Router::new()
+        .route("/{blake3_hash}", get(handlers::get_blob_content))
+        .route(
+            "/{blake3_hash}/files",
+            get(handlers::get_blob_files).post(handlers::link_file),
+        );
+
+    // Bucket operations are kept apart from bucket's files
+    // so that any file path is possible including `zip` or `last`
+    let bucket_api = Router::new()
+        .route("/{bucket}/last", get(handlers::get_last_file))
+        .route("/{bucket}/stats", get(handlers::get_bucket_stats))
+        .route(
+            "/{bucket}/zip",
+            post(handlers::insert_zipped_bucket).get(handlers::get_zipped_bucket),
+        )
+        .route("/{bucket}/tar", post(handlers::insert_tarred_bucket))
+        .route("/{bucket}/copy", post(handlers::copy_bucket))
+        .route(
+            "/{bucket}/versioning",
+            get(handlers::get_versioning).put(handlers::set_versioning),
+        );
 
     let api = Router::new()
         .route("/", get(handlers::get_buckets))
+        .route("/stats", get(handlers::get_stats))
         .route(
             "/{bucket}",
             post(handlers::insert_many_from_form)
                 .delete(handlers::delete_bucket)
                 .get(handlers::get_files),
         )
-        .route("/{bucket}/last", get(handlers::get_last_file))
         .route(
-            "/{bucket}/{file_name}",
+            "/{bucket}/{*path}",
             post(handlers::insert_file)
+                .put(handlers::put_file)
                 .get(handlers::search_and_get_file_content)
                 .delete(handlers::search_and_delete_file),
         )
-        .route("/{bucket}/zip", post(handlers::insert_zipped_bucket))
-        .nest("/file/", file_api);
+        .route("/meta/{bucket}/{*path}", get(handlers::search_file_info))
+        .nest("/file/", file_api)
+        .nest("/blob/", blob_api)
+        .nest("/bucket/", bucket_api);
 
-    let storage = Sqlite::open(db.clone(), Mode::ReadWrite)?;
-    let storage = Arc::new(Mutex::new(storage));
-    Ok(Router::new()
+    Router::new()
         .merge(SwaggerUi::new("/swagger").url("/api-doc/openapi.json", ApiDoc::openapi()))
         .nest("/api/", api)
         .layer(
@@ -148,13 +413,11 @@ pub fn create_routes(db: PathBuf) -> Result<Router, Error> {
                         tracing::error!("Server error: {error}");
                     },
                 ))
+                // Blobs are stored by chunks so there is no file size limit
                 .layer(DefaultBodyLimit::disable())
-                .layer(RequestBodyLimitLayer::new(
-                    2 * 1024 * 1024 * 1024, /* 2GB */
-                ))
                 .into_inner(),
         )
-        .with_state(storage))
+        .with_state(storage)
 }
 
 /// .
@@ -0,0 +1,163 @@
+use std::fmt::Write;
+
+use chrono::{DateTime, Utc};
+use kernel::File;
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+const TOKEN_SEP: char = ':';
+
+/// Order of listed files. Files with the same sort key are ordered by id
+#[derive(Deserialize, ToSchema, Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum SortBy {
+    /// By path inside bucket
+    #[default]
+    Path,
+    /// By file id that is the order files were added in
+    Id,
+    /// By content size starting from the smallest file
+    Size,
+}
+
+impl SortBy {
+    fn as_str(self) -> &'static str {
+        match self {
+            SortBy::Path => "path",
+            SortBy::Id => "id",
+            SortBy::Size => "size",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "path" => Some(SortBy::Path),
+            "id" => Some(SortBy::Id),
+            "size" => Some(SortBy::Size),
+            _ => None,
+        }
+    }
+}
+
+/// Bucket's files listing filter, order and page. Default query lists all files by path
+#[derive(Default)]
+pub struct FileQuery {
+    /// Only files modified after the time
+    pub modified_since: Option<DateTime<Utc>>,
+    /// Only files which path starts with the prefix
+    pub prefix: Option<String>,
+    /// Only files which path matches glob pattern. `*` matches `/` too
+    pub glob: Option<String>,
+    /// Only files not smaller than the size in bytes
+    pub min_size: Option<u64>,
+    /// Only files not larger than the size in bytes
+    pub max_size: Option<u64>,
+    /// Only files which path has no delimiter after prefix i.e. the files of the directory itself
+    pub delimiter: Option<String>,
+    pub sort: SortBy,
+    /// Only files that follow the cursor in the order specified
+    pub after: Option<Cursor>,
+    /// Max number of files listed
+    pub limit: Option<usize>,
+}
+
+/// Position of the last file of the listing page. The next page starts right after it
+#[derive(Debug, PartialEq, Eq)]
+pub struct Cursor {
+    pub sort: SortBy,
+    pub id: i64,
+    pub size: i64,
+    pub path: String,
+}
+
+impl Cursor {
+    #[must_use]
+    pub fn after(sort: SortBy, file: &File) -> Self {
+        Self {
+            sort,
+            id: file.id,
+            size: i64::try_from(file.size).unwrap_or(i64::MAX),
+            path: file.path.clone(),
+        }
+    }
+
+    /// Makes opaque token that is safe to use in URL as is
+    #[must_use]
+    pub fn token(&self) -> String {
+        let plain = format!(
+            "{}{TOKEN_SEP}{}{TOKEN_SEP}{}{TOKEN_SEP}{}",
+            self.sort.as_str(),
+            self.id,
+            self.size,
+            self.path
+        );
+        plain.bytes().fold(String::new(), |mut token, b| {
+            let _ = write!(token, "{b:02x}");
+            token
+        })
+    }
+
+    /// Restores cursor from the token made by `token`. Returns `None` if token is invalid
+    #[must_use]
+    pub fn parse(token: &str) -> Option<Self> {
+        if token.len() % 2 != 0 {
+            return None;
+        }
+        let bytes = token
+            .as_bytes()
+            .chunks(2)
+            .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        let plain = String::from_utf8(bytes).ok()?;
+        // Path goes last because it may contain separator
+        let mut parts = plain.splitn(4, TOKEN_SEP);
+        Some(Self {
+            sort: SortBy::parse(parts.next()?)?,
+            id: parts.next()?.parse().ok()?,
+            size: parts.next()?.parse().ok()?,
+            path: parts.next()?.to_owned(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case(SortBy::Path, "d1/f1:2" ; "path with separator")]
+    #[test_case(SortBy::Id, "f1" ; "id")]
+    #[test_case(SortBy::Size, "" ; "empty path")]
+    fn token_round_trip(sort: SortBy, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        // Arrange
+        let cursor = Cursor {
+            sort,
+            id: 42,
+            size: 1024,
+            path: path.to_owned(),
+        };
+
+        // Act
+        let parsed = Cursor::parse(&cursor.token()).ok_or("invalid token")?;
+
+        // Assert
+        assert_eq!(parsed, cursor);
+        Ok(())
+    }
+
+    #[test_case("" ; "empty")]
+    #[test_case("zz" ; "not hex")]
+    #[test_case("706" ; "odd length")]
+    #[test_case("706174683a31" ; "missing parts")]
+    #[test_case("6e616d653a313a323a66" ; "unknown sort")]
+    #[test_case("706174683a783a323a66" ; "invalid id")]
+    fn parse_invalid(token: &str) {
+        // Arrange
+
+        // Act
+        let result = Cursor::parse(token);
+
+        // Assert
+        assert!(result.is_none());
+    }
+}
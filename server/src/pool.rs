@@ -0,0 +1,227 @@
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use std::sync::{Arc, PoisonError};
+
+use futures::lock::{Mutex, OwnedMutexGuard};
+use rusqlite::Error;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::sqlite::{Mode, Sqlite};
+
+/// Storage connections shared by handlers. Reads are served concurrently by
+/// the pool of read-only connections that is possible because database is in WAL mode.
+/// All changes are serialized through the single writer connection
+pub struct ConnectionPool<S> {
+    writer: Arc<Mutex<S>>,
+    readers: Arc<[Arc<Mutex<S>>]>,
+    /// Indexes of the readers not in use
+    free_readers: Arc<std::sync::Mutex<Vec<usize>>>,
+    /// Permit per free reader so that waiting request gets the first reader released
+    available_readers: Arc<Semaphore>,
+}
+
+impl<S> Clone for ConnectionPool<S> {
+    fn clone(&self) -> Self {
+        Self {
+            writer: self.writer.clone(),
+            readers: self.readers.clone(),
+            free_readers: self.free_readers.clone(),
+            available_readers: self.available_readers.clone(),
+        }
+    }
+}
+
+impl ConnectionPool<Sqlite> {
+    /// Opens writer and the number of readers specified. At least one reader is always opened
+    pub fn open<P: AsRef<Path>>(path: P, readers: usize) -> Result<Self, Error> {
+        let path = path.as_ref();
+        Self::open_with(readers, |mode| Sqlite::open(path, mode))
+    }
+}
+
+impl<S> ConnectionPool<S> {
+    /// Opens writer and the number of readers specified using the function that opens
+    /// storage in the mode passed. At least one reader is always opened
+    pub fn open_with<E, F>(readers: usize, open: F) -> Result<Self, E>
+    where
+        F: Fn(Mode) -> Result<S, E>,
+    {
+        let writer = open(Mode::ReadWrite)?;
+        let readers = (0..readers.max(1))
+            .map(|_| open(Mode::ReadOnly).map(|r| Arc::new(Mutex::new(r))))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::new(Arc::new(Mutex::new(writer)), readers))
+    }
+
+    /// Makes pool that serves both reads and writes by the single storage instance
+    pub fn single(storage: S) -> Self {
+        let storage = Arc::new(Mutex::new(storage));
+        Self::new(storage.clone(), vec![storage])
+    }
+
+    fn new(writer: Arc<Mutex<S>>, readers: Vec<Arc<Mutex<S>>>) -> Self {
+        Self {
+            writer,
+            free_readers: Arc::new(std::sync::Mutex::new((0..readers.len()).collect())),
+            available_readers: Arc::new(Semaphore::new(readers.len())),
+            readers: readers.into(),
+        }
+    }
+
+    /// Locks the writer connection. Other changes wait until the guard is dropped
+    /// so it should be held only while a single storage operation is executed
+    pub async fn writer(&self) -> OwnedMutexGuard<S> {
+        self.writer.clone().lock_owned().await
+    }
+
+    /// Gets an idle read-only connection or waits for the first one released if all are busy
+    pub async fn reader(&self) -> PooledReader<S> {
+        let permit = self
+            .available_readers
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("readers semaphore is never closed");
+        let index = self
+            .free_readers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop()
+            .expect("reader is free while permit is available");
+        // Reader shared with writer is locked by changes too
+        let reader = self.readers[index].clone().lock_owned().await;
+        PooledReader {
+            reader: Some(reader),
+            index,
+            free_readers: self.free_readers.clone(),
+            _permit: permit,
+        }
+    }
+}
+
+/// Read-only connection taken from the pool. It's given back when the guard is dropped
+pub struct PooledReader<S> {
+    reader: Option<OwnedMutexGuard<S>>,
+    index: usize,
+    free_readers: Arc<std::sync::Mutex<Vec<usize>>>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl<S> Deref for PooledReader<S> {
+    type Target = S;
+
+    fn deref(&self) -> &S {
+        self.reader.as_deref().expect("reader is kept until drop")
+    }
+}
+
+impl<S> DerefMut for PooledReader<S> {
+    fn deref_mut(&mut self) -> &mut S {
+        self.reader
+            .as_deref_mut()
+            .expect("reader is kept until drop")
+    }
+}
+
+impl<S> Drop for PooledReader<S> {
+    fn drop(&mut self) {
+        // Reader is unlocked and marked free before the permit is released
+        // so that the next request finds it idle
+        self.reader.take();
+        self.free_readers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(self.index);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::Storage;
+    use futures::FutureExt;
+
+    fn pool(
+        readers: usize,
+    ) -> Result<(tempfile::TempDir, ConnectionPool<Sqlite>), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let db = dir.path().join("pool.db");
+        Sqlite::open(&db, Mode::ReadWrite)?.new_database()?;
+        let pool = ConnectionPool::open(&db, readers)?;
+        Ok((dir, pool))
+    }
+
+    #[test]
+    fn reader_not_blocked_by_writer() -> Result<(), Box<dyn std::error::Error>> {
+        // Arrange
+        let (_dir, pool) = pool(1)?;
+        let _writer = pool.writer().now_or_never();
+
+        // Act
+        let reader = pool.reader().now_or_never();
+
+        // Assert
+        assert!(reader.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn idle_reader_used() -> Result<(), Box<dyn std::error::Error>> {
+        // Arrange
+        let (_dir, pool) = pool(2)?;
+        let _busy = pool.reader().now_or_never();
+
+        // Act
+        let reader = pool.reader().now_or_never();
+
+        // Assert
+        assert!(reader.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn all_readers_busy() -> Result<(), Box<dyn std::error::Error>> {
+        // Arrange
+        let (_dir, pool) = pool(1)?;
+        let _busy = pool.reader().now_or_never();
+
+        // Act
+        let reader = pool.reader().now_or_never();
+
+        // Assert
+        assert!(reader.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn first_released_reader_used() -> Result<(), Box<dyn std::error::Error>> {
+        // Arrange
+        let (_dir, pool) = pool(2)?;
+        let first = pool.reader().now_or_never();
+        let second = pool.reader().now_or_never();
+        let mut waiting = Box::pin(pool.reader());
+        assert!((&mut waiting).now_or_never().is_none());
+
+        // Act
+        drop(second);
+
+        // Assert
+        assert!(waiting.now_or_never().is_some());
+        drop(first);
+        Ok(())
+    }
+
+    #[test]
+    fn reader_cannot_write() -> Result<(), Box<dyn std::error::Error>> {
+        // Arrange
+        let (_dir, pool) = pool(1)?;
+        let reader = pool.reader().now_or_never();
+
+        // Act
+        let result = reader.map(|mut r| r.begin_upload());
+
+        // Assert
+        assert!(matches!(result, Some(Err(_))));
+        Ok(())
+    }
+}
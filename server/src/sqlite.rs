@@ -1,11 +1,17 @@
-use std::io::{Read, Write};
+use std::fs;
 use std::path::Path;
 
-use kernel::{Bucket, DeleteResult, File};
-use rusqlite::blob::ZeroBlob;
-use rusqlite::{Connection, Error, ErrorCode, MAIN_DB, OpenFlags, Row, Transaction, params};
+use chrono::{DateTime, Utc};
+use kernel::{Blob, Bucket, DeleteResult, File, FileVersion, PutResult, Stats};
+use rusqlite::{
+    Connection, Error, ErrorCode, MAIN_DB, OpenFlags, OptionalExtension, Row, Transaction, ffi,
+    params, types::Value,
+};
 
-use crate::domain::Storage;
+use crate::domain::{Storage, StorageError, Upload};
+use crate::listing::{FileQuery, SortBy};
+
+mod migrations;
 
 const CACHE_SIZE: &str = "16384";
 
@@ -19,6 +25,33 @@ pub struct Sqlite {
     conn: Connection,
 }
 
+/// Database schema version compared to the one the server works with
+#[derive(Copy, Clone, Debug)]
+pub struct SchemaVersion {
+    pub current: u32,
+    pub latest: u32,
+}
+
+impl SchemaVersion {
+    #[must_use]
+    pub fn is_up_to_date(&self) -> bool {
+        self.current == self.latest
+    }
+}
+
+impl StorageError for Error {
+    /// Unique index is violated i.e. file path is taken by another file
+    fn is_conflict(&self) -> bool {
+        matches!(self, Error::SqliteFailure(e, _)
+            if e.code == ErrorCode::ConstraintViolation
+                && e.extended_code == ffi::SQLITE_CONSTRAINT_UNIQUE)
+    }
+
+    fn is_not_found(&self) -> bool {
+        matches!(self, Error::QueryReturnedNoRows)
+    }
+}
+
 impl Storage for Sqlite {
     type Err = Error;
 
@@ -26,123 +59,141 @@ impl Storage for Sqlite {
         self.pragma_update("encoding", "UTF-8")?;
         self.pragma_update("journal_mode", "WAL")?;
 
-        self.conn.execute(
-            "CREATE TABLE blob (
-                  blake3_hash    TEXT PRIMARY KEY,
-                  data           BLOB NOT NULL,
-                  size           INTEGER NOT NULL
-                  )",
-            [],
-        )?;
+        migrations::apply(&self.conn)?;
 
-        self.conn.execute(
-            "CREATE TABLE file (
-                  id           INTEGER PRIMARY KEY AUTOINCREMENT,
-                  blake3_hash  TEXT NOT NULL REFERENCES blob(blake3_hash) ON DELETE RESTRICT ON UPDATE RESTRICT,
-                  path         TEXT NOT NULL,
-                  bucket       TEXT NOT NULL
-                  )",
-            [],
-        )?;
+        Ok(())
+    }
 
-        self.conn.execute(
-            "CREATE UNIQUE INDEX bucket_path_unique_ix ON file(path, bucket)",
-            [],
-        )?;
+    fn begin_upload(&mut self) -> Result<Upload, Self::Err> {
+        self.set_synchronous_full()?;
 
+        Sqlite::execute_with_retry(|| {
+            self.conn.execute("INSERT INTO upload DEFAULT VALUES", [])?;
+            Ok(Upload::new(self.conn.last_insert_rowid()))
+        })
+    }
+
+    fn append_upload(&mut self, upload: &mut Upload, data: &[u8]) -> Result<(), Self::Err> {
+        self.assign_cache_size()?;
+        // Staged chunks needn't be synced one by one. Commit syncs WAL under FULL mode
+        // so that all chunks written before it become durable with the file
+        self.set_synchronous_normal()?;
+
+        Sqlite::execute_with_retry(|| {
+            self.conn
+                .prepare_cached(
+                    "INSERT INTO blob_chunk (upload_id, start, data) VALUES (?1, ?2, ?3)",
+                )?
+                .execute(params![upload.id(), upload.size(), data])?;
+            Ok(())
+        })?;
+        upload.update(data);
         Ok(())
     }
 
-    /// inserts new file into bucket and return it's id
-    fn insert_file(&mut self, path: &str, bucket: &str, data: Vec<u8>) -> Result<i64, Self::Err> {
+    /// inserts new file into bucket from staged upload and return it's id
+    fn commit_upload(
+        &mut self,
+        upload: Upload,
+        path: &str,
+        bucket: &str,
+        content_type: &str,
+    ) -> Result<i64, Self::Err> {
         self.assign_cache_size()?;
         self.enable_foreign_keys()?;
         self.set_synchronous_full()?;
 
-        let hash = blake3::hash(&data);
-        let hash = hash.to_string();
+        let hash = upload.hash();
 
-        Sqlite::execute_with_retry(|| {
+        let result = Sqlite::execute_with_retry(|| {
             let tx = self.conn.transaction()?;
 
-            let mut stmt = tx.prepare("SELECT blake3_hash FROM blob WHERE blake3_hash = ?1")?;
-
-            let exists = stmt.exists(params![&hash])?;
-            stmt.finalize()?;
-
-            if !exists {
-                // Insert only uniqueue blob so as not to have duplicates.
-                // If binary data already in DB just link existing
-                // data with new file item
-                // ZeroBlob accepts only i32 so max file size limited to 2GB
-                let len = i32::try_from(data.len()).unwrap_or(i32::MAX);
-                tx.execute(
-                    "INSERT INTO blob (blake3_hash, data, size) VALUES (?1, ?2, ?3)",
-                    params![&hash, &ZeroBlob(len), len],
-                )?;
-
-                let rowid = tx.last_insert_rowid();
-
-                let mut blob = tx.blob_open(MAIN_DB, "blob", "data", rowid, false)?;
-                if let Err(e) = blob.write_all(&data) {
-                    tracing::error!("{e}");
-                }
-                blob.flush().unwrap_or_default();
-                blob.close()?;
-            }
-
-            tx.prepare_cached(
-                "INSERT INTO file (blake3_hash, path, bucket)
-                 VALUES (?1, ?2, ?3)",
-            )?
-            .execute(params![&hash, path, bucket])?;
-
-            let mut stmt = tx.prepare("SELECT MAX(id) FROM file")?;
+            Self::store_blob(&tx, &upload, &hash)?;
 
-            let id = stmt.query_row([], |row| {
-                let id = row.get(0)?;
-                Ok(id)
-            })?;
-            stmt.finalize()?;
+            let result = Self::write_file(&tx, &hash, path, bucket, content_type, false)?;
 
             tx.commit()?;
 
-            Ok(id)
-        })
+            Ok(result.id)
+        });
+
+        if result.is_err() {
+            // Staged content is useless if file wasn't created
+            self.abort_upload(upload)?;
+        }
+        result
     }
 
-    fn delete_bucket(&mut self, bucket: &str) -> Result<DeleteResult, Self::Err> {
+    fn link_blob(
+        &mut self,
+        blake3_hash: &str,
+        path: &str,
+        bucket: &str,
+        content_type: &str,
+    ) -> Result<i64, Self::Err> {
         self.enable_foreign_keys()?;
         self.set_synchronous_full()?;
 
         Sqlite::execute_with_retry(|| {
             let tx = self.conn.transaction()?;
-            let mut stmt = tx.prepare("DELETE FROM file WHERE bucket = ?1")?;
-            let deleted_files = stmt.execute(params![bucket])?;
-            stmt.finalize()?;
 
-            let deleted_blobs = Self::cleanup_blobs(&tx)?;
+            let exists = tx
+                .prepare_cached("SELECT blake3_hash FROM blob WHERE blake3_hash = ?1")?
+                .exists([blake3_hash])?;
+            if !exists {
+                return Err(Error::QueryReturnedNoRows);
+            }
+
+            let result = Self::write_file(&tx, blake3_hash, path, bucket, content_type, false)?;
 
             tx.commit()?;
 
-            Ok(DeleteResult {
-                files: deleted_files,
-                blobs: deleted_blobs,
-            })
+            Ok(result.id)
+        })
+    }
+
+    fn put_upload(
+        &mut self,
+        upload: Upload,
+        path: &str,
+        bucket: &str,
+        content_type: &str,
+    ) -> Result<PutResult, Self::Err> {
+        let (result, _) = self.replace_file(upload, path, bucket, content_type)?;
+        Ok(result)
+    }
+
+    fn abort_upload(&mut self, upload: Upload) -> Result<(), Self::Err> {
+        Sqlite::execute_with_retry(|| Self::delete_staged_upload(&self.conn, upload.id()))
+    }
+
+    fn delete_bucket(&mut self, bucket: &str) -> Result<DeleteResult, Self::Err> {
+        let (files, blobs) = self.remove_bucket(bucket)?;
+        Ok(DeleteResult {
+            files,
+            blobs: blobs.len(),
         })
     }
 
-    fn get_buckets(&mut self) -> Result<Vec<Bucket>, Self::Err> {
+    fn get_buckets(
+        &mut self,
+        modified_since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Bucket>, Self::Err> {
         self.enable_foreign_keys()?;
         self.set_synchronous_full()?;
 
-        let mut stmt = self
-            .conn
-            .prepare("SELECT bucket, count(bucket) FROM file GROUP BY bucket")?;
-        let buckets = stmt.query_map([], |row| {
+        let mut stmt = self.conn.prepare(
+            "SELECT file.bucket, count(file.bucket), SUM(blob.size), MAX(file.updated_at) \
+             FROM file INNER JOIN blob on file.blake3_hash = blob.blake3_hash GROUP BY file.bucket \
+             HAVING ?1 IS NULL OR MAX(file.updated_at) > ?1",
+        )?;
+        let since = modified_since.map(|s| s.timestamp());
+        let buckets = stmt.query_map([since], |row| {
             let b = Bucket {
                 id: row.get(0)?,
                 files_count: row.get(1)?,
+                total_size: row.get(2)?,
+                last_modified: row.get(3)?,
             };
             Ok(b)
         })?;
@@ -150,49 +201,121 @@ impl Storage for Sqlite {
         Ok(buckets.filter_map(std::result::Result::ok).collect())
     }
 
-    fn get_files(&mut self, bucket: &str) -> Result<Vec<File>, Self::Err> {
+    fn get_files(&mut self, bucket: &str, query: &FileQuery) -> Result<Vec<File>, Self::Err> {
         self.enable_foreign_keys()?;
         self.set_synchronous_full()?;
 
-        let mut stmt = self.conn.prepare(
-            "SELECT file.id, file.path, file.bucket, blob.size, file.blake3_hash \
+        // Sort key and id make unique position so the next page starts exactly after cursor
+        let key = match query.sort {
+            SortBy::Path => "file.path",
+            SortBy::Id => "file.id",
+            SortBy::Size => "blob.size",
+        };
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT file.id, file.path, file.bucket, blob.size, file.blake3_hash, file.content_type, file.created_at, file.updated_at \
                            FROM file INNER JOIN blob on file.blake3_hash = blob.blake3_hash \
-                           WHERE file.bucket = ?1",
+                           WHERE file.bucket = ?1 AND (?2 IS NULL OR file.updated_at > ?2) \
+                           AND (?3 IS NULL OR substr(file.path, 1, length(?3)) = ?3) \
+                           AND (?4 IS NULL OR file.path GLOB ?4) \
+                           AND (?5 IS NULL OR blob.size >= ?5) AND (?6 IS NULL OR blob.size <= ?6) \
+                           AND (?7 IS NULL OR ({key}, file.id) > (?7, ?8)) \
+                           AND (?10 IS NULL OR instr(substr(file.path, length(COALESCE(?3, '')) + 1), ?10) = 0) \
+                           ORDER BY {key}, file.id LIMIT ?9"
+        ))?;
+        let since = query.modified_since.map(|s| s.timestamp());
+        let after_key = query.after.as_ref().map(|c| match query.sort {
+            SortBy::Path => Value::Text(c.path.clone()),
+            SortBy::Id => Value::Integer(c.id),
+            SortBy::Size => Value::Integer(c.size),
+        });
+        let after_id = query.after.as_ref().map(|c| c.id);
+        // Negative limit means no limit
+        let limit = query
+            .limit
+            .map_or(-1, |l| i64::try_from(l).unwrap_or(i64::MAX));
+        let files = stmt.query_map(
+            params![
+                bucket,
+                since,
+                query.prefix,
+                query.glob,
+                query.min_size,
+                query.max_size,
+                after_key,
+                after_id,
+                limit,
+                query.delimiter
+            ],
+            Sqlite::to_file,
         )?;
-        let files = stmt.query_map([bucket], Sqlite::to_file)?;
 
         Ok(files.filter_map(std::result::Result::ok).collect())
     }
 
+    fn get_common_prefixes(
+        &mut self,
+        bucket: &str,
+        prefix: &str,
+        delimiter: &str,
+    ) -> Result<Vec<String>, Self::Err> {
+        let mut stmt = self.conn.prepare(
+            "SELECT DISTINCT ?2 || substr(rest, 1, instr(rest, ?3) + length(?3) - 1) FROM \
+             (SELECT substr(path, length(?2) + 1) AS rest FROM file \
+              WHERE bucket = ?1 AND substr(path, 1, length(?2)) = ?2) \
+             WHERE instr(rest, ?3) > 0 ORDER BY 1",
+        )?;
+        let prefixes = stmt.query_map(params![bucket, prefix, delimiter], |row| row.get(0))?;
+
+        prefixes.collect()
+    }
+
     fn get_last_file(&mut self, bucket: &str) -> Result<File, Self::Err> {
         self.enable_foreign_keys()?;
         self.set_synchronous_full()?;
 
         let mut stmt = self.conn.prepare(
-            "SELECT file.id, file.path, file.bucket, blob.size, file.blake3_hash \
+            "SELECT file.id, file.path, file.bucket, blob.size, file.blake3_hash, file.content_type, file.created_at, file.updated_at \
                            FROM file INNER JOIN blob on file.blake3_hash = blob.blake3_hash \
-                           WHERE file.bucket = ?1 ORDER BY file.id DESC LIMIT 1",
+                           WHERE file.bucket = ?1 ORDER BY file.created_at DESC, file.id DESC LIMIT 1",
         )?;
         stmt.query_row([bucket], Sqlite::to_file)
     }
 
-    fn get_file_data(&self, id: i64) -> Result<Box<dyn Read + '_>, Self::Err> {
-        self.enable_foreign_keys()?;
-        self.set_synchronous_full()?;
+    fn get_file_data(
+        &self,
+        blake3_hash: &str,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> Result<usize, Self::Err> {
+        // Content is split into chunks so find the one that contains the offset
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT blob_chunk.rowid, blob_chunk.start \
+             FROM blob_chunk INNER JOIN blob on blob_chunk.upload_id = blob.upload_id \
+             WHERE blob.blake3_hash = ?1 AND blob_chunk.start <= ?2 \
+             ORDER BY blob_chunk.start DESC LIMIT 1",
+        )?;
+        let chunk: Option<(i64, u64)> = stmt
+            .query_row(params![blake3_hash, offset], |r| Ok((r.get(0)?, r.get(1)?)))
+            .optional()?;
+        let Some((rowid, start)) = chunk else {
+            return Ok(0);
+        };
 
-        let mut stmt = self.conn.prepare("SELECT rowid FROM blob WHERE blake3_hash IN (SELECT blake3_hash FROM file WHERE id = ?1)")?;
-        let rowid: i64 = stmt.query_row([id], |r| r.get(0))?;
-        stmt.finalize()?;
+        let blob = self
+            .conn
+            .blob_open(MAIN_DB, "blob_chunk", "data", rowid, true)?;
+        let offset = usize::try_from(offset - start).unwrap_or(usize::MAX);
+        let read = blob.read_at(buf, offset)?;
+        blob.close()?;
 
-        let b = self.conn.blob_open(MAIN_DB, "blob", "data", rowid, true)?;
-        Ok(Box::new(b))
+        Ok(read)
     }
 
     fn get_file_info(&mut self, id: i64) -> Result<File, Self::Err> {
         self.enable_foreign_keys()?;
         self.set_synchronous_full()?;
 
-        let mut stmt = self.conn.prepare("SELECT file.id, file.path, file.bucket, blob.size, file.blake3_hash \
+        let mut stmt = self.conn.prepare("SELECT file.id, file.path, file.bucket, blob.size, file.blake3_hash, file.content_type, file.created_at, file.updated_at \
                                                        FROM file INNER JOIN blob on file.blake3_hash = blob.blake3_hash \
                                                        WHERE id = ?1")?;
         let result: File = stmt.query_row([id], Sqlite::to_file)?;
@@ -201,11 +324,43 @@ impl Storage for Sqlite {
         Ok(result)
     }
 
+    fn get_blob(&mut self, blake3_hash: &str) -> Result<Blob, Self::Err> {
+        // Content never changes so the earliest reference tells when it was stored
+        let mut stmt = self.conn.prepare(
+            "SELECT blob.blake3_hash, blob.size, ref.content_type, ref.created_at \
+             FROM blob INNER JOIN \
+             (SELECT blake3_hash, content_type, created_at FROM file WHERE blake3_hash = ?1 \
+              UNION ALL \
+              SELECT blake3_hash, content_type, created_at FROM file_version WHERE blake3_hash = ?1) ref \
+             ON blob.blake3_hash = ref.blake3_hash \
+             ORDER BY ref.created_at LIMIT 1",
+        )?;
+        stmt.query_row([blake3_hash], |row| {
+            Ok(Blob {
+                blake3_hash: row.get(0)?,
+                size: row.get(1)?,
+                content_type: row.get(2)?,
+                created_at: row.get(3)?,
+            })
+        })
+    }
+
+    fn get_blob_files(&mut self, blake3_hash: &str) -> Result<Vec<File>, Self::Err> {
+        let mut stmt = self.conn.prepare(
+            "SELECT file.id, file.path, file.bucket, blob.size, file.blake3_hash, file.content_type, file.created_at, file.updated_at \
+                           FROM file INNER JOIN blob on file.blake3_hash = blob.blake3_hash \
+                           WHERE file.blake3_hash = ?1 ORDER BY file.bucket, file.path",
+        )?;
+        let files = stmt.query_map([blake3_hash], Sqlite::to_file)?;
+
+        files.collect()
+    }
+
     fn search_file_info(&mut self, bucket: &str, path: &str) -> Result<File, Self::Err> {
         self.enable_foreign_keys()?;
         self.set_synchronous_full()?;
 
-        let mut stmt = self.conn.prepare("SELECT file.id, file.path, file.bucket, blob.size, file.blake3_hash \
+        let mut stmt = self.conn.prepare("SELECT file.id, file.path, file.bucket, blob.size, file.blake3_hash, file.content_type, file.created_at, file.updated_at \
                                                        FROM file INNER JOIN blob on file.blake3_hash = blob.blake3_hash \
                                                        WHERE bucket = ?1 AND path = ?2")?;
         let result: File = stmt.query_row([bucket, path], Sqlite::to_file)?;
@@ -214,24 +369,195 @@ impl Storage for Sqlite {
         Ok(result)
     }
 
+    fn get_stats(&mut self, bucket: Option<&str>, largest: usize) -> Result<Stats, Self::Err> {
+        let files_count: i64 = self
+            .conn
+            .prepare("SELECT COUNT(id) FROM file WHERE ?1 IS NULL OR bucket = ?1")?
+            .query_row([bucket], |row| row.get(0))?;
+
+        // Both sizes include files previous versions so that their ratio
+        // shows deduplication savings only
+        let logical_size: i64 = self
+            .conn
+            .prepare(
+                "SELECT COALESCE(SUM(blob.size), 0) FROM \
+                 (SELECT blake3_hash FROM file WHERE ?1 IS NULL OR bucket = ?1 \
+                  UNION ALL \
+                  SELECT file_version.blake3_hash FROM file_version \
+                  INNER JOIN file on file_version.file_id = file.id WHERE ?1 IS NULL OR file.bucket = ?1) AS content \
+                 INNER JOIN blob on content.blake3_hash = blob.blake3_hash",
+            )?
+            .query_row([bucket], |row| row.get(0))?;
+
+        let physical_size: i64 = self
+            .conn
+            .prepare(
+                "SELECT COALESCE(SUM(size), 0) FROM blob WHERE ?1 IS NULL OR blake3_hash IN \
+                 (SELECT blake3_hash FROM file WHERE bucket = ?1 \
+                  UNION \
+                  SELECT file_version.blake3_hash FROM file_version \
+                  INNER JOIN file on file_version.file_id = file.id WHERE file.bucket = ?1)",
+            )?
+            .query_row([bucket], |row| row.get(0))?;
+
+        let mut stmt = self.conn.prepare(
+            "SELECT file.id, file.path, file.bucket, blob.size, file.blake3_hash, file.content_type, file.created_at, file.updated_at \
+                           FROM file INNER JOIN blob on file.blake3_hash = blob.blake3_hash \
+                           WHERE ?1 IS NULL OR file.bucket = ?1 ORDER BY blob.size DESC, file.id LIMIT ?2",
+        )?;
+        let largest = i64::try_from(largest).unwrap_or(i64::MAX);
+        let largest_files = stmt
+            .query_map(params![bucket, largest], Sqlite::to_file)?
+            .collect::<Result<Vec<File>, Error>>()?;
+
+        #[allow(clippy::cast_precision_loss)]
+        let dedup_ratio = if physical_size == 0 {
+            1.0
+        } else {
+            logical_size as f64 / physical_size as f64
+        };
+
+        // In-memory database has no files
+        let database = self.conn.path().filter(|p| !p.is_empty());
+        Ok(Stats {
+            files_count,
+            logical_size,
+            physical_size,
+            dedup_ratio,
+            largest_files,
+            database_size: database.map(file_size).unwrap_or_default(),
+            wal_size: database
+                .map(|p| file_size(&format!("{p}-wal")))
+                .unwrap_or_default(),
+        })
+    }
+
+    fn move_file(&mut self, id: i64, bucket: &str, path: &str) -> Result<Option<File>, Self::Err> {
+        self.enable_foreign_keys()?;
+        self.set_synchronous_full()?;
+
+        let now = Utc::now().timestamp();
+        let moved = Sqlite::execute_with_retry(|| {
+            self.conn.execute(
+                "UPDATE file SET bucket = ?1, path = ?2, updated_at = ?3 WHERE id = ?4",
+                params![bucket, path, now, id],
+            )
+        });
+        match moved {
+            Ok(0) => Err(Error::QueryReturnedNoRows),
+            Ok(_) => self.get_file_info(id).map(Some),
+            // bucket_path_unique_ix violated i.e. destination is taken by another file
+            Err(e) if e.is_conflict() => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn copy_file(
+        &mut self,
+        id: i64,
+        bucket: &str,
+        path: &str,
+        overwrite: bool,
+    ) -> Result<Option<i64>, Self::Err> {
+        let (ids, _) = self.write_copies(bucket, &[(id, path.to_owned())], overwrite)?;
+        Ok(ids.first().copied())
+    }
+
+    fn copy_bucket(
+        &mut self,
+        source: &str,
+        target: &str,
+        prefix: Option<&str>,
+        overwrite: bool,
+    ) -> Result<Vec<i64>, Self::Err> {
+        let (ids, _) = self.copy_bucket_files(source, target, prefix, overwrite)?;
+        Ok(ids)
+    }
+
     fn delete_file(&mut self, id: i64) -> Result<DeleteResult, Self::Err> {
+        let (files, blobs) = self.remove_file(id)?;
+        Ok(DeleteResult {
+            files,
+            blobs: blobs.len(),
+        })
+    }
+
+    fn set_versioning(&mut self, bucket: &str, enabled: bool) -> Result<(), Self::Err> {
+        self.set_synchronous_full()?;
+
+        let sql = if enabled {
+            "INSERT OR IGNORE INTO versioned_bucket (bucket) VALUES (?1)"
+        } else {
+            "DELETE FROM versioned_bucket WHERE bucket = ?1"
+        };
+        Sqlite::execute_with_retry(|| {
+            self.conn.execute(sql, [bucket])?;
+            Ok(())
+        })
+    }
+
+    fn get_versioning(&mut self, bucket: &str) -> Result<bool, Self::Err> {
+        Self::is_versioned(&self.conn, bucket)
+    }
+
+    fn get_file_versions(&mut self, id: i64) -> Result<Vec<FileVersion>, Self::Err> {
+        let mut stmt = self.conn.prepare(
+            "SELECT file.version, file.blake3_hash, blob.size, file.content_type, file.updated_at, 1 \
+             FROM file INNER JOIN blob on file.blake3_hash = blob.blake3_hash \
+             WHERE file.id = ?1 \
+             UNION ALL \
+             SELECT file_version.version, file_version.blake3_hash, blob.size, file_version.content_type, file_version.created_at, 0 \
+             FROM file_version INNER JOIN blob on file_version.blake3_hash = blob.blake3_hash \
+             WHERE file_version.file_id = ?1 \
+             ORDER BY 1 DESC",
+        )?;
+        let versions = stmt.query_map([id], Sqlite::to_file_version)?;
+
+        versions.collect()
+    }
+
+    fn delete_file_version(&mut self, id: i64, version: i64) -> Result<DeleteResult, Self::Err> {
+        let (files, blobs) = self.remove_file_version(id, version)?;
+        Ok(DeleteResult {
+            files,
+            blobs: blobs.len(),
+        })
+    }
+
+    fn restore_file_version(&mut self, id: i64, version: i64) -> Result<FileVersion, Self::Err> {
         self.enable_foreign_keys()?;
         self.set_synchronous_full()?;
 
         Sqlite::execute_with_retry(|| {
             let tx = self.conn.transaction()?;
-            let mut stmt = tx.prepare("DELETE FROM file WHERE id = ?1")?;
-            let deleted_files = stmt.execute(params![id])?;
-            stmt.finalize()?;
 
-            let deleted_blobs = Self::cleanup_blobs(&tx)?;
+            let (hash, content_type): (String, String) = tx.query_row(
+                "SELECT blake3_hash, content_type FROM file_version WHERE file_id = ?1 AND version = ?2 \
+                 UNION ALL \
+                 SELECT blake3_hash, content_type FROM file WHERE id = ?1 AND version = ?2",
+                params![id, version],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?;
+
+            Self::archive_current_version(&tx, id)?;
+            tx.execute(
+                "UPDATE file SET blake3_hash = ?1, content_type = ?2, updated_at = ?3, \
+                 version = max_version + 1, max_version = max_version + 1 \
+                 WHERE id = ?4",
+                params![hash, content_type, Utc::now().timestamp(), id],
+            )?;
+
+            let restored = tx.query_row(
+                "SELECT file.version, file.blake3_hash, blob.size, file.content_type, file.updated_at, 1 \
+                 FROM file INNER JOIN blob on file.blake3_hash = blob.blake3_hash \
+                 WHERE file.id = ?1",
+                [id],
+                Sqlite::to_file_version,
+            )?;
 
             tx.commit()?;
 
-            Ok(DeleteResult {
-                files: deleted_files,
-                blobs: deleted_blobs,
-            })
+            Ok(restored)
         })
     }
 }
@@ -245,6 +571,280 @@ impl Sqlite {
         Ok(Self { conn: c? })
     }
 
+    /// Opens new private in-memory database with the latest schema.
+    /// Database is destroyed when the connection is closed
+    pub fn in_memory() -> Result<Sqlite, Error> {
+        let storage = Self {
+            conn: Connection::open_in_memory()?,
+        };
+        storage.new_database()?;
+        Ok(storage)
+    }
+
+    /// Applies pending schema migrations. Returns the number of migrations applied
+    pub fn migrate(&self) -> Result<usize, Error> {
+        migrations::apply(&self.conn)
+    }
+
+    pub fn schema_version(&self) -> Result<SchemaVersion, Error> {
+        Ok(SchemaVersion {
+            current: migrations::current_version(&self.conn)?,
+            latest: migrations::latest_version(),
+        })
+    }
+
+    /// Removes uploads left by interrupted requests e.g. when server crashed mid-upload.
+    /// Committed upload becomes blob so any upload still present is abandoned.
+    /// Must not be called while server is serving requests. Returns the number of uploads removed
+    pub fn remove_pending_uploads(&mut self) -> Result<usize, Error> {
+        Sqlite::execute_with_retry(|| {
+            let tx = self.conn.transaction()?;
+            tx.execute(
+                "DELETE FROM blob_chunk WHERE upload_id IN (SELECT id FROM upload)",
+                [],
+            )?;
+            let removed = tx.execute("DELETE FROM upload", [])?;
+            tx.commit()?;
+            Ok(removed)
+        })
+    }
+
+    /// Whether any blob's content is kept inside database. Such content cannot be read
+    /// by filesystem blob store that expects content files only
+    pub fn has_embedded_blobs(&self) -> Result<bool, Error> {
+        self.conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM blob JOIN blob_chunk ON blob_chunk.upload_id = blob.upload_id)",
+            [],
+            |row| row.get(0),
+        )
+    }
+
+    /// Whether any blob's content is kept outside database i.e. it was stored by filesystem
+    /// blob store. Such content cannot be read by `SQLite` blob store. Empty blobs have no content
+    /// so they're fine for both stores
+    pub fn has_external_blobs(&self) -> Result<bool, Error> {
+        self.conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM blob WHERE blob.size > 0 AND NOT EXISTS \
+             (SELECT 1 FROM blob_chunk WHERE blob_chunk.upload_id = blob.upload_id))",
+            [],
+            |row| row.get(0),
+        )
+    }
+
+    /// Buckets among the names specified that have any files
+    pub fn existing_buckets(&self, names: &[&str]) -> Result<Vec<String>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT EXISTS (SELECT 1 FROM file WHERE bucket = ?1)")?;
+        let mut existing = vec![];
+        for name in names {
+            if stmt.query_row([name], |row| row.get(0))? {
+                existing.push((*name).to_owned());
+            }
+        }
+        Ok(existing)
+    }
+
+    /// Creates file or replaces existing file's content. Returns put result
+    /// and hashes of the blobs deleted because they aren't used anymore
+    pub(crate) fn replace_file(
+        &mut self,
+        upload: Upload,
+        path: &str,
+        bucket: &str,
+        content_type: &str,
+    ) -> Result<(PutResult, Vec<String>), Error> {
+        self.assign_cache_size()?;
+        self.enable_foreign_keys()?;
+        self.set_synchronous_full()?;
+
+        let hash = upload.hash();
+
+        let result = Sqlite::execute_with_retry(|| {
+            let tx = self.conn.transaction()?;
+
+            Self::store_blob(&tx, &upload, &hash)?;
+
+            let result = Self::write_file(&tx, &hash, path, bucket, content_type, true)?;
+
+            // Previous content might be not used anymore
+            let deleted_blobs = Self::cleanup_blobs(&tx)?;
+
+            tx.commit()?;
+
+            Ok((result, deleted_blobs))
+        });
+
+        if result.is_err() {
+            // Staged content is useless if file wasn't written
+            self.abort_upload(upload)?;
+        }
+        result
+    }
+
+    /// Copies source bucket's files into target bucket. Returns the ids of the files written
+    /// and hashes of the blobs deleted because overwritten content isn't used anymore
+    pub(crate) fn copy_bucket_files(
+        &mut self,
+        source: &str,
+        target: &str,
+        prefix: Option<&str>,
+        overwrite: bool,
+    ) -> Result<(Vec<i64>, Vec<String>), Error> {
+        let query = FileQuery {
+            prefix: prefix.map(str::to_owned),
+            ..Default::default()
+        };
+        let copies: Vec<(i64, String)> = self
+            .get_files(source, &query)?
+            .into_iter()
+            .map(|f| (f.id, f.path))
+            .collect();
+        self.write_copies(target, &copies, overwrite)
+    }
+
+    /// Writes files at the paths of the bucket that reference the content of the files
+    /// which ids are specified. Paths taken by other files are skipped unless `overwrite` is set.
+    /// Returns the ids of the files written and hashes of the blobs deleted
+    /// because overwritten content isn't used anymore
+    pub(crate) fn write_copies(
+        &mut self,
+        bucket: &str,
+        copies: &[(i64, String)],
+        overwrite: bool,
+    ) -> Result<(Vec<i64>, Vec<String>), Error> {
+        self.enable_foreign_keys()?;
+        self.set_synchronous_full()?;
+
+        Sqlite::execute_with_retry(|| {
+            let tx = self.conn.transaction()?;
+
+            let mut ids = Vec::with_capacity(copies.len());
+            for (id, path) in copies {
+                let (hash, content_type): (String, String) = tx
+                    .prepare_cached("SELECT blake3_hash, content_type FROM file WHERE id = ?1")?
+                    .query_row([id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+                let taken = tx
+                    .prepare_cached("SELECT id FROM file WHERE bucket = ?1 AND path = ?2")?
+                    .exists(params![bucket, path])?;
+                if taken && !overwrite {
+                    continue;
+                }
+                let result = Self::write_file(&tx, &hash, path, bucket, &content_type, true)?;
+                ids.push(result.id);
+            }
+
+            let deleted_blobs = if overwrite {
+                Self::cleanup_blobs(&tx)?
+            } else {
+                vec![]
+            };
+
+            tx.commit()?;
+
+            Ok((ids, deleted_blobs))
+        })
+    }
+
+    /// Deletes file's version. Returns the number of versions deleted and hashes of the blobs deleted
+    pub(crate) fn remove_file_version(
+        &mut self,
+        id: i64,
+        version: i64,
+    ) -> Result<(usize, Vec<String>), Error> {
+        self.enable_foreign_keys()?;
+        self.set_synchronous_full()?;
+
+        Sqlite::execute_with_retry(|| {
+            let tx = self.conn.transaction()?;
+
+            let current: Option<i64> = tx
+                .query_row("SELECT version FROM file WHERE id = ?1", [id], |row| {
+                    row.get(0)
+                })
+                .optional()?;
+
+            let deleted_versions = match current {
+                None => 0,
+                Some(current) if current == version => {
+                    let previous: Option<i64> = tx.query_row(
+                        "SELECT MAX(version) FROM file_version WHERE file_id = ?1",
+                        [id],
+                        |row| row.get(0),
+                    )?;
+                    if let Some(previous) = previous {
+                        // Previous version becomes current one
+                        tx.execute(
+                            "UPDATE file SET (blake3_hash, content_type, updated_at, version) = \
+                             (SELECT blake3_hash, content_type, created_at, version FROM file_version \
+                              WHERE file_id = ?1 AND version = ?2) \
+                             WHERE id = ?1",
+                            params![id, previous],
+                        )?;
+                        tx.execute(
+                            "DELETE FROM file_version WHERE file_id = ?1 AND version = ?2",
+                            params![id, previous],
+                        )?;
+                    } else {
+                        tx.execute("DELETE FROM file WHERE id = ?1", [id])?;
+                    }
+                    1
+                }
+                Some(_) => tx.execute(
+                    "DELETE FROM file_version WHERE file_id = ?1 AND version = ?2",
+                    params![id, version],
+                )?,
+            };
+
+            let deleted_blobs = Self::cleanup_blobs(&tx)?;
+
+            tx.commit()?;
+
+            Ok((deleted_versions, deleted_blobs))
+        })
+    }
+
+    /// Deletes all bucket's files and blobs that aren't used anymore.
+    /// Returns the number of files deleted and hashes of the blobs deleted
+    pub(crate) fn remove_bucket(&mut self, bucket: &str) -> Result<(usize, Vec<String>), Error> {
+        self.enable_foreign_keys()?;
+        self.set_synchronous_full()?;
+
+        Sqlite::execute_with_retry(|| {
+            let tx = self.conn.transaction()?;
+            let mut stmt = tx.prepare("DELETE FROM file WHERE bucket = ?1")?;
+            let deleted_files = stmt.execute(params![bucket])?;
+            stmt.finalize()?;
+            tx.execute("DELETE FROM versioned_bucket WHERE bucket = ?1", [bucket])?;
+
+            let deleted_blobs = Self::cleanup_blobs(&tx)?;
+
+            tx.commit()?;
+
+            Ok((deleted_files, deleted_blobs))
+        })
+    }
+
+    /// Deletes file and it's blob if it isn't used anymore.
+    /// Returns the number of files deleted and hashes of the blobs deleted
+    pub(crate) fn remove_file(&mut self, id: i64) -> Result<(usize, Vec<String>), Error> {
+        self.enable_foreign_keys()?;
+        self.set_synchronous_full()?;
+
+        Sqlite::execute_with_retry(|| {
+            let tx = self.conn.transaction()?;
+            let mut stmt = tx.prepare("DELETE FROM file WHERE id = ?1")?;
+            let deleted_files = stmt.execute(params![id])?;
+            stmt.finalize()?;
+
+            let deleted_blobs = Self::cleanup_blobs(&tx)?;
+
+            tx.commit()?;
+
+            Ok((deleted_files, deleted_blobs))
+        })
+    }
+
     fn enable_foreign_keys(&self) -> Result<(), Error> {
         self.pragma_update("foreign_keys", "ON")
     }
@@ -257,18 +857,131 @@ impl Sqlite {
         self.pragma_update("synchronous", "FULL")
     }
 
+    fn set_synchronous_normal(&self) -> Result<(), Error> {
+        self.pragma_update("synchronous", "NORMAL")
+    }
+
     fn pragma_update(&self, name: &str, value: &str) -> Result<(), Error> {
         self.conn.pragma_update(None, name, value)
     }
 
-    fn cleanup_blobs(tx: &Transaction) -> Result<usize, Error> {
-        let mut stmt =
-            tx.prepare("DELETE FROM blob WHERE blake3_hash NOT IN (SELECT blake3_hash FROM file)")?;
-        let result = stmt.execute(params![])?;
+    /// Deletes blobs that neither files nor their previous versions use
+    fn cleanup_blobs(tx: &Transaction) -> Result<Vec<String>, Error> {
+        let mut stmt = tx.prepare(
+            "DELETE FROM blob_chunk WHERE upload_id IN \
+             (SELECT upload_id FROM blob WHERE blake3_hash NOT IN \
+             (SELECT blake3_hash FROM file UNION SELECT blake3_hash FROM file_version))",
+        )?;
+        stmt.execute(params![])?;
+        stmt.finalize()?;
+
+        let mut stmt = tx.prepare(
+            "DELETE FROM blob WHERE blake3_hash NOT IN \
+             (SELECT blake3_hash FROM file UNION SELECT blake3_hash FROM file_version) \
+             RETURNING blake3_hash",
+        )?;
+        let result = stmt
+            .query_map(params![], |row| row.get(0))?
+            .collect::<Result<Vec<String>, Error>>()?;
         stmt.finalize()?;
         Ok(result)
     }
 
+    /// Turns staged upload into blob unless blob with the same hash already exists
+    fn store_blob(tx: &Transaction, upload: &Upload, hash: &str) -> Result<(), Error> {
+        let mut stmt = tx.prepare("SELECT blake3_hash FROM blob WHERE blake3_hash = ?1")?;
+
+        let exists = stmt.exists(params![hash])?;
+        stmt.finalize()?;
+
+        if exists {
+            // Insert only uniqueue blob so as not to have duplicates.
+            // If binary data already in DB just link existing
+            // data with new file item
+            Self::delete_staged_upload(tx, upload.id())?;
+        } else {
+            // Staged chunks become blob's content as is so no data copied
+            tx.execute(
+                "INSERT INTO blob (blake3_hash, size, upload_id) VALUES (?1, ?2, ?3)",
+                params![hash, upload.size(), upload.id()],
+            )?;
+            tx.prepare_cached("DELETE FROM upload WHERE id = ?1")?
+                .execute([upload.id()])?;
+        }
+        Ok(())
+    }
+
+    /// Inserts new file or writes content into the file that already exists at the path.
+    /// Existing file is only written if `replace` is set or bucket is versioned
+    /// and in the latter case its current content is kept as the previous version
+    fn write_file(
+        tx: &Transaction,
+        hash: &str,
+        path: &str,
+        bucket: &str,
+        content_type: &str,
+        replace: bool,
+    ) -> Result<PutResult, Error> {
+        let now = Utc::now().timestamp();
+        let existing: Option<i64> = tx
+            .prepare_cached("SELECT id FROM file WHERE bucket = ?1 AND path = ?2")?
+            .query_row(params![bucket, path], |row| row.get(0))
+            .optional()?;
+        let versioned = existing.is_some() && Self::is_versioned(tx, bucket)?;
+
+        match existing {
+            Some(id) if replace || versioned => {
+                if versioned {
+                    Self::archive_current_version(tx, id)?;
+                }
+                // Content is just replaced without new version if no versions are kept.
+                // New version is numbered after the greatest one so that deleted numbers aren't reused
+                tx.prepare_cached(
+                    "UPDATE file SET blake3_hash = ?1, content_type = ?2, updated_at = ?3, \
+                     version = CASE WHEN ?4 THEN max_version + 1 ELSE version END, max_version = max_version + ?4 \
+                     WHERE id = ?5",
+                )?
+                .execute(params![hash, content_type, now, i64::from(versioned), id])?;
+                Ok(PutResult { id, created: false })
+            }
+            _ => {
+                // Fails because of unique path if the file exists
+                tx.prepare_cached(
+                    "INSERT INTO file (blake3_hash, path, bucket, content_type, created_at, updated_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?5)",
+                )?
+                .execute(params![hash, path, bucket, content_type, now])?;
+                Ok(PutResult {
+                    id: tx.last_insert_rowid(),
+                    created: true,
+                })
+            }
+        }
+    }
+
+    /// Keeps file's current content as the previous version
+    fn archive_current_version(tx: &Transaction, id: i64) -> Result<(), Error> {
+        tx.prepare_cached(
+            "INSERT INTO file_version (file_id, version, blake3_hash, content_type, created_at) \
+             SELECT id, version, blake3_hash, content_type, updated_at FROM file WHERE id = ?1",
+        )?
+        .execute([id])?;
+        Ok(())
+    }
+
+    fn is_versioned(conn: &Connection, bucket: &str) -> Result<bool, Error> {
+        conn.prepare_cached("SELECT bucket FROM versioned_bucket WHERE bucket = ?1")?
+            .exists([bucket])
+    }
+
+    fn delete_staged_upload(conn: &Connection, upload_id: i64) -> Result<(), Error> {
+        conn.prepare_cached("DELETE FROM blob_chunk WHERE upload_id = ?1")?
+            .execute([upload_id])?;
+        conn.prepare_cached("DELETE FROM upload WHERE id = ?1")?
+            .execute([upload_id])?;
+        Ok(())
+    }
+
     fn to_file(row: &Row<'_>) -> Result<File, Error> {
         let file = File {
             id: row.get(0)?,
@@ -276,10 +989,25 @@ impl Sqlite {
             bucket: row.get(2)?,
             size: row.get(3)?,
             blake3_hash: row.get(4)?,
+            content_type: row.get(5)?,
+            created_at: row.get(6)?,
+            updated_at: row.get(7)?,
         };
         Ok(file)
     }
 
+    fn to_file_version(row: &Row<'_>) -> Result<FileVersion, Error> {
+        let version = FileVersion {
+            version: row.get(0)?,
+            blake3_hash: row.get(1)?,
+            size: row.get(2)?,
+            content_type: row.get(3)?,
+            created_at: row.get(4)?,
+            current: row.get(5)?,
+        };
+        Ok(version)
+    }
+
     /// Ignores `ErrorCode::DatabaseBusy` and retry query if so
     /// Only needed in case of changing queries not reading ones
     fn execute_with_retry<T, F>(mut action: F) -> Result<T, Error>
@@ -301,3 +1029,7 @@ impl Sqlite {
         }
     }
 }
+
+fn file_size(path: &str) -> u64 {
+    fs::metadata(path).map(|m| m.len()).unwrap_or_default()
+}
@@ -0,0 +1,284 @@
+use chrono::Utc;
+use rusqlite::{Connection, Error, Transaction, ffi, params};
+
+use crate::content_type;
+
+/// Schema change applied inside the transaction that also bumps `user_version`
+type Migration = fn(&Transaction) -> Result<(), Error>;
+
+/// Ordered schema migrations. Migration's index plus one is the schema version it produces.
+/// Never change or reorder already released migrations, only append new ones
+const MIGRATIONS: &[Migration] = &[
+    baseline,
+    chunked_blobs,
+    content_types,
+    timestamps,
+    versions,
+    max_versions,
+];
+
+/// Schema version the code works with
+#[must_use]
+pub fn latest_version() -> u32 {
+    u32::try_from(MIGRATIONS.len()).unwrap_or(u32::MAX)
+}
+
+/// Reads schema version stored in database header
+pub fn current_version(conn: &Connection) -> Result<u32, Error> {
+    conn.pragma_query_value(None, "user_version", |row| row.get(0))
+}
+
+/// Applies pending migrations in order. Each migration runs in its own transaction
+/// together with version update so that failed one can be retried on next start.
+/// Returns the number of migrations applied
+pub fn apply(conn: &Connection) -> Result<usize, Error> {
+    let current = current_version(conn)?;
+    let latest = latest_version();
+    if current > latest {
+        return Err(Error::SqliteFailure(
+            ffi::Error::new(ffi::SQLITE_MISMATCH),
+            Some(format!(
+                "database schema version {current} is newer than supported {latest}"
+            )),
+        ));
+    }
+
+    // Tables are rebuilt by some migrations that isn't possible with enforced foreign keys.
+    // This pragma has no effect inside transaction so it's set before
+    conn.pragma_update(None, "foreign_keys", "OFF")?;
+
+    let mut applied = 0;
+    for (version, migration) in (1..=latest).zip(MIGRATIONS).skip(current as usize) {
+        let tx = conn.unchecked_transaction()?;
+        migration(&tx)?;
+        tx.pragma_update(None, "user_version", version)?;
+        tx.commit()?;
+        tracing::info!("database schema migrated to version {version}");
+        applied += 1;
+    }
+    Ok(applied)
+}
+
+/// Schema that existed before migrations were introduced. Databases created then
+/// already have it so tables are created only if missing
+fn baseline(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS blob (
+              blake3_hash    TEXT PRIMARY KEY,
+              data           BLOB NOT NULL,
+              size           INTEGER NOT NULL
+              );
+         CREATE TABLE IF NOT EXISTS file (
+              id           INTEGER PRIMARY KEY AUTOINCREMENT,
+              blake3_hash  TEXT NOT NULL REFERENCES blob(blake3_hash) ON DELETE RESTRICT ON UPDATE RESTRICT,
+              path         TEXT NOT NULL,
+              bucket       TEXT NOT NULL
+              );
+         CREATE UNIQUE INDEX IF NOT EXISTS bucket_path_unique_ix ON file(path, bucket);",
+    )
+}
+
+/// Moves blob's content into chunks table. Whole content of existing blob becomes its only chunk
+fn chunked_blobs(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch(
+        "CREATE TABLE upload (
+              id           INTEGER PRIMARY KEY AUTOINCREMENT
+              );
+         CREATE TABLE blob_chunk (
+              upload_id    INTEGER NOT NULL,
+              start        INTEGER NOT NULL,
+              data         BLOB NOT NULL,
+              PRIMARY KEY (upload_id, start)
+              );
+         -- Reserve upload ids so that new uploads never get the same ones
+         INSERT INTO upload (id) SELECT rowid FROM blob;
+         DELETE FROM upload;
+         INSERT INTO blob_chunk (upload_id, start, data) SELECT rowid, 0, data FROM blob;
+         CREATE TABLE blob_new (
+              blake3_hash    TEXT PRIMARY KEY,
+              size           INTEGER NOT NULL,
+              upload_id      INTEGER NOT NULL
+              );
+         INSERT INTO blob_new (blake3_hash, size, upload_id) SELECT blake3_hash, size, rowid FROM blob;
+         DROP TABLE blob;
+         ALTER TABLE blob_new RENAME TO blob;",
+    )
+}
+
+/// Adds MIME type detected by file name and content signature to existing files
+fn content_types(tx: &Transaction) -> Result<(), Error> {
+    tx.execute(
+        "ALTER TABLE file ADD COLUMN content_type TEXT NOT NULL DEFAULT 'application/octet-stream'",
+        [],
+    )?;
+
+    let signature_len = i64::try_from(content_type::SIGNATURE_LEN).unwrap_or(i64::MAX);
+    let mut stmt = tx.prepare(
+        "SELECT file.id, file.path, substr(blob_chunk.data, 1, ?1) \
+         FROM file INNER JOIN blob on file.blake3_hash = blob.blake3_hash \
+         LEFT JOIN blob_chunk on blob_chunk.upload_id = blob.upload_id AND blob_chunk.start = 0",
+    )?;
+    let files = stmt.query_map([signature_len], |row| {
+        let id: i64 = row.get(0)?;
+        let path: String = row.get(1)?;
+        let head: Option<Vec<u8>> = row.get(2)?;
+        Ok((id, path, head.unwrap_or_default()))
+    })?;
+
+    let mut update = tx.prepare("UPDATE file SET content_type = ?1 WHERE id = ?2")?;
+    for file in files {
+        let (id, path, head) = file?;
+        update.execute(params![content_type::resolve(None, &path, &head), id])?;
+    }
+    Ok(())
+}
+
+/// Adds creation and modification time. Existing files get migration time
+fn timestamps(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch(
+        "ALTER TABLE file ADD COLUMN created_at INTEGER NOT NULL DEFAULT 0;
+         ALTER TABLE file ADD COLUMN updated_at INTEGER NOT NULL DEFAULT 0;
+         CREATE INDEX file_updated_at_ix ON file(updated_at);",
+    )?;
+    tx.execute(
+        "UPDATE file SET created_at = ?1, updated_at = ?1",
+        [Utc::now().timestamp()],
+    )?;
+    Ok(())
+}
+
+/// Adds file content versions. File row keeps the current version
+/// and the previous ones are kept in separate table
+fn versions(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch(
+        "ALTER TABLE file ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+         CREATE TABLE file_version (
+              file_id      INTEGER NOT NULL REFERENCES file(id) ON DELETE CASCADE,
+              version      INTEGER NOT NULL,
+              blake3_hash  TEXT NOT NULL REFERENCES blob(blake3_hash) ON DELETE RESTRICT ON UPDATE RESTRICT,
+              content_type TEXT NOT NULL,
+              created_at   INTEGER NOT NULL,
+              PRIMARY KEY (file_id, version)
+              );
+         CREATE INDEX file_version_blake3_hash_ix ON file_version(blake3_hash);
+         CREATE TABLE versioned_bucket (
+              bucket       TEXT PRIMARY KEY
+              );",
+    )
+}
+
+/// Tracks the greatest version file ever had so that versions deleted aren't numbered again
+fn max_versions(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch(
+        "ALTER TABLE file ADD COLUMN max_version INTEGER NOT NULL DEFAULT 1;
+         UPDATE file SET max_version = MAX(version, \
+              COALESCE((SELECT MAX(version) FROM file_version WHERE file_id = file.id), 0));",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+    fn legacy_database() -> Result<Connection, Error> {
+        let conn = Connection::open_in_memory()?;
+        let tx = conn.unchecked_transaction()?;
+        baseline(&tx)?;
+        tx.execute(
+            "INSERT INTO blob (blake3_hash, data, size) VALUES ('h1', ?1, ?2)",
+            params![PNG, PNG.len()],
+        )?;
+        tx.execute(
+            "INSERT INTO file (blake3_hash, path, bucket) VALUES ('h1', 'image', 'b1')",
+            [],
+        )?;
+        tx.execute(
+            "INSERT INTO file (blake3_hash, path, bucket) VALUES ('h1', 'page.html', 'b1')",
+            [],
+        )?;
+        tx.commit()?;
+        Ok(conn)
+    }
+
+    #[test]
+    fn apply_to_new_database() -> Result<(), Error> {
+        // Arrange
+        let conn = Connection::open_in_memory()?;
+
+        // Act
+        let applied = apply(&conn)?;
+
+        // Assert
+        assert_eq!(applied, MIGRATIONS.len());
+        assert_eq!(current_version(&conn)?, latest_version());
+        Ok(())
+    }
+
+    #[test]
+    fn apply_to_legacy_database() -> Result<(), Error> {
+        // Arrange
+        let conn = legacy_database()?;
+
+        // Act
+        let applied = apply(&conn)?;
+
+        // Assert
+        assert_eq!(applied, MIGRATIONS.len());
+        let chunk: Vec<u8> = conn.query_row(
+            "SELECT blob_chunk.data FROM blob_chunk \
+             INNER JOIN blob on blob_chunk.upload_id = blob.upload_id \
+             WHERE blob.blake3_hash = 'h1' AND blob_chunk.start = 0",
+            [],
+            |row| row.get(0),
+        )?;
+        assert_eq!(chunk, PNG);
+        let mut stmt = conn.prepare("SELECT content_type FROM file ORDER BY id")?;
+        let types = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<String>, Error>>()?;
+        assert_eq!(types, vec!["image/png", "text/html"]);
+        let untimed: i64 = conn.query_row(
+            "SELECT count(*) FROM file WHERE updated_at = 0",
+            [],
+            |row| row.get(0),
+        )?;
+        assert_eq!(untimed, 0);
+        let unversioned: i64 = conn.query_row(
+            "SELECT count(*) FROM file WHERE version != 1 OR max_version != 1",
+            [],
+            |row| row.get(0),
+        )?;
+        assert_eq!(unversioned, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn apply_twice() -> Result<(), Error> {
+        // Arrange
+        let conn = legacy_database()?;
+        apply(&conn)?;
+
+        // Act
+        let applied = apply(&conn)?;
+
+        // Assert
+        assert_eq!(applied, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn apply_to_newer_database() -> Result<(), Error> {
+        // Arrange
+        let conn = Connection::open_in_memory()?;
+        conn.pragma_update(None, "user_version", latest_version() + 1)?;
+
+        // Act
+        let result = apply(&conn);
+
+        // Assert
+        assert!(result.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+use std::io::{self, Cursor};
+use std::path::{Component, Path};
+use std::pin::Pin;
+
+use async_compression::tokio::bufread::{GzipDecoder, ZstdDecoder};
+use tokio::io::{AsyncRead, AsyncReadExt, BufReader};
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// The number of leading bytes enough to detect any supported compression
+const MAGIC_LEN: usize = 4;
+
+/// Tar archive reader that decompresses archive on the fly if necessary
+pub type TarReader = Pin<Box<dyn AsyncRead + Send>>;
+
+/// Archive compression
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Detects compression by archive's leading bytes. Anything unknown is considered plain tar
+    #[must_use]
+    pub fn detect(head: &[u8]) -> Self {
+        if head.starts_with(GZIP_MAGIC) {
+            Compression::Gzip
+        } else if head.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// Makes tar reader from possibly compressed stream. Compression is detected
+/// by the stream's leading bytes that are read and then given back to decoder
+pub async fn decompress<R>(mut reader: R) -> io::Result<TarReader>
+where
+    R: AsyncRead + Send + Unpin + 'static,
+{
+    let mut head = vec![0u8; MAGIC_LEN];
+    let mut filled = 0;
+    while filled < head.len() {
+        let read = reader.read(&mut head[filled..]).await?;
+        if read == 0 {
+            break;
+        }
+        filled += read;
+    }
+    head.truncate(filled);
+
+    let compression = Compression::detect(&head);
+    let reader = BufReader::new(Cursor::new(head).chain(reader));
+    let reader: TarReader = match compression {
+        Compression::None => Box::pin(reader),
+        Compression::Gzip => {
+            let mut decoder = GzipDecoder::new(reader);
+            // Concatenated gzip files are valid gzip file too
+            decoder.multiple_members(true);
+            Box::pin(decoder)
+        }
+        Compression::Zstd => {
+            let mut decoder = ZstdDecoder::new(reader);
+            // Archive may consist of several frames e.g. if it's compressed in parallel
+            decoder.multiple_members(true);
+            Box::pin(decoder)
+        }
+    };
+    Ok(reader)
+}
+
+/// Path inside bucket to insert tar entry into. Only normal components are kept
+/// so neither absolute paths nor parent directory references are stored
+#[must_use]
+pub fn entry_path(path: &Path) -> Option<String> {
+    let mut parts = vec![];
+    for component in path.components() {
+        if let Component::Normal(part) = component {
+            parts.push(part.to_str()?);
+        }
+    }
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join("/"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case(&[0x1f, 0x8b, 0x08, 0x00], Compression::Gzip ; "gzip")]
+    #[test_case(&[0x28, 0xb5, 0x2f, 0xfd], Compression::Zstd ; "zstd")]
+    #[test_case(b"f1\0\0", Compression::None ; "plain")]
+    #[test_case(&[0x28, 0xb5], Compression::None ; "short")]
+    #[test_case(&[], Compression::None ; "empty")]
+    fn detect_tests(head: &[u8], expected: Compression) {
+        // Arrange
+
+        // Act
+        let result = Compression::detect(head);
+
+        // Assert
+        assert_eq!(result, expected);
+    }
+
+    #[test_case("f1", Some("f1") ; "file")]
+    #[test_case("d1/f1", Some("d1/f1") ; "nested")]
+    #[test_case("./d1/f1", Some("d1/f1") ; "current dir")]
+    #[test_case("/d1/f1", Some("d1/f1") ; "absolute")]
+    #[test_case("../d1/../f1", Some("d1/f1") ; "parent dir")]
+    #[test_case("./", None ; "no file")]
+    fn entry_path_tests(path: &str, expected: Option<&str>) {
+        // Arrange
+
+        // Act
+        let result = entry_path(Path::new(path));
+
+        // Assert
+        assert_eq!(result.as_deref(), expected);
+    }
+}
@@ -0,0 +1,139 @@
+use std::io::{self, BufWriter, Write};
+
+use axum::body::Bytes;
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use futures::{Stream, StreamExt};
+use kernel::File;
+use tokio::runtime::Handle;
+use tokio::sync::mpsc::{self, Sender};
+use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+use crate::async_storage::AsyncStorage;
+use crate::domain::Backend;
+use crate::handlers::blob_stream;
+
+/// Size of the archive parts sent to client
+const PART_SIZE: usize = 256 * 1024;
+
+/// The number of archive parts built in advance while client receives previous ones
+const PARTS_AHEAD: usize = 4;
+
+/// Builds zip archive of the files specified on the fly. Entries are compressed
+/// on blocking thread and archive parts are streamed as soon as they're ready
+/// so neither whole archive nor whole file is kept in memory
+pub fn zip_stream<S: Backend>(
+    db: AsyncStorage<S>,
+    files: Vec<File>,
+) -> impl Stream<Item = io::Result<Bytes>> {
+    let (parts, received) = mpsc::channel(PARTS_AHEAD);
+    let runtime = Handle::current();
+    tokio::task::spawn_blocking(move || {
+        let writer = BufWriter::with_capacity(PART_SIZE, PartSender(parts.clone()));
+        if let Err(e) = write_zip(&runtime, &db, &files, writer) {
+            tracing::error!("zip archive not completed. Error: {e}");
+            // Response is broken so that client doesn't get truncated archive
+            parts.blocking_send(Err(e)).unwrap_or_default();
+        }
+    });
+    futures::stream::unfold(received, |mut received| async move {
+        received.recv().await.map(|part| (part, received))
+    })
+}
+
+fn write_zip<S: Backend, W: Write>(
+    runtime: &Handle,
+    db: &AsyncStorage<S>,
+    files: &[File],
+    writer: W,
+) -> io::Result<()> {
+    let mut zip = ZipWriter::new_stream(writer);
+    for f in files {
+        let size = f.size as u64;
+        let options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .last_modified_time(zip_time(f.updated_at))
+            .large_file(size >= u64::from(u32::MAX));
+        zip.start_file(f.path.as_str(), options)?;
+
+        let content = blob_stream(db.clone(), f.blake3_hash.clone(), 0, size);
+        futures::pin_mut!(content);
+        while let Some(chunk) = runtime.block_on(content.next()) {
+            zip.write_all(&chunk?)?;
+        }
+    }
+    zip.finish()?.into_inner().flush()
+}
+
+/// Zip keeps time without zone so UTC is written.
+/// Time that cannot be represented in zip is replaced by zip's default one
+fn zip_time(time: DateTime<Utc>) -> zip::DateTime {
+    let part = |v: u32| u8::try_from(v).unwrap_or_default();
+    zip::DateTime::from_date_and_time(
+        u16::try_from(time.year()).unwrap_or_default(),
+        part(time.month()),
+        part(time.day()),
+        part(time.hour()),
+        part(time.minute()),
+        part(time.second()),
+    )
+    .unwrap_or_default()
+}
+
+/// Sends everything written as the next archive part
+struct PartSender(Sender<io::Result<Bytes>>);
+
+impl Write for PartSender {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .blocking_send(Ok(Bytes::copy_from_slice(buf)))
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn zip_time_in_range() -> Result<(), Box<dyn std::error::Error>> {
+        // Arrange
+        let time = Utc
+            .with_ymd_and_hms(2024, 1, 31, 10, 20, 30)
+            .single()
+            .ok_or("invalid time")?;
+
+        // Act
+        let result = zip_time(time);
+
+        // Assert
+        assert_eq!(result.year(), 2024);
+        assert_eq!(result.month(), 1);
+        assert_eq!(result.day(), 31);
+        assert_eq!(result.hour(), 10);
+        assert_eq!(result.minute(), 20);
+        assert_eq!(result.second(), 30);
+        Ok(())
+    }
+
+    #[test]
+    fn zip_time_out_of_range() -> Result<(), Box<dyn std::error::Error>> {
+        // Arrange
+        let time = Utc
+            .with_ymd_and_hms(1970, 1, 1, 0, 0, 0)
+            .single()
+            .ok_or("invalid time")?;
+
+        // Act
+        let result = zip_time(time);
+
+        // Assert
+        assert_eq!(result, zip::DateTime::default());
+        Ok(())
+    }
+}
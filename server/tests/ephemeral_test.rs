@@ -0,0 +1,46 @@
+use axum::body::{Body, to_bytes};
+use axum::http::{Request, StatusCode};
+use tower::ServiceExt;
+
+#[tokio::test]
+async fn insert_and_get_file_content() {
+    // Arrange
+    let app = server::create_ephemeral_routes().unwrap();
+    let insert = Request::post("/api/bucket1/file.txt")
+        .body(Body::from("content"))
+        .unwrap();
+    let response = app.clone().oneshot(insert).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    // Act
+    let get = Request::get("/api/bucket1/file.txt")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(get).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(&body[..], b"content");
+}
+
+#[tokio::test]
+async fn instances_isolated() {
+    // Arrange
+    let app = server::create_ephemeral_routes().unwrap();
+    let other = server::create_ephemeral_routes().unwrap();
+    let insert = Request::post("/api/bucket1/file.txt")
+        .body(Body::from("content"))
+        .unwrap();
+    let response = app.oneshot(insert).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    // Act
+    let get = Request::get("/api/bucket1/file.txt")
+        .body(Body::empty())
+        .unwrap();
+    let response = other.oneshot(get).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
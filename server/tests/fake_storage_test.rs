@@ -0,0 +1,441 @@
+use std::collections::HashMap;
+use std::io;
+
+use axum::body::{Body, to_bytes};
+use axum::http::{Request, StatusCode};
+use chrono::{DateTime, Utc};
+use kernel::{Blob, Bucket, DeleteResult, File, FileVersion, PutResult, Stats};
+use server::domain::{Storage, Upload};
+use server::listing::FileQuery;
+use tower::ServiceExt;
+
+/// In-memory storage that neither deduplicates blobs nor keeps file versions
+#[derive(Default)]
+struct FakeStorage {
+    files: Vec<File>,
+    blobs: HashMap<String, Vec<u8>>,
+    staged: HashMap<i64, Vec<u8>>,
+    next_id: i64,
+}
+
+fn not_found() -> io::Error {
+    io::Error::from(io::ErrorKind::NotFound)
+}
+
+impl Storage for FakeStorage {
+    type Err = io::Error;
+
+    fn new_database(&self) -> Result<(), Self::Err> {
+        Ok(())
+    }
+
+    fn begin_upload(&mut self) -> Result<Upload, Self::Err> {
+        self.next_id += 1;
+        self.staged.insert(self.next_id, vec![]);
+        Ok(Upload::new(self.next_id))
+    }
+
+    fn append_upload(&mut self, upload: &mut Upload, data: &[u8]) -> Result<(), Self::Err> {
+        let staged = self.staged.get_mut(&upload.id()).ok_or_else(not_found)?;
+        staged.extend_from_slice(data);
+        upload.update(data);
+        Ok(())
+    }
+
+    fn commit_upload(
+        &mut self,
+        upload: Upload,
+        path: &str,
+        bucket: &str,
+        content_type: &str,
+    ) -> Result<i64, Self::Err> {
+        let data = self.staged.remove(&upload.id()).ok_or_else(not_found)?;
+        let now = Utc::now();
+        self.next_id += 1;
+        self.files.push(File {
+            id: self.next_id,
+            path: path.to_owned(),
+            bucket: bucket.to_owned(),
+            blake3_hash: upload.hash(),
+            size: data.len(),
+            content_type: content_type.to_owned(),
+            created_at: now,
+            updated_at: now,
+        });
+        self.blobs.insert(upload.hash(), data);
+        Ok(self.next_id)
+    }
+
+    fn link_blob(
+        &mut self,
+        blake3_hash: &str,
+        path: &str,
+        bucket: &str,
+        content_type: &str,
+    ) -> Result<i64, Self::Err> {
+        let size = self.blobs.get(blake3_hash).ok_or_else(not_found)?.len();
+        let now = Utc::now();
+        self.next_id += 1;
+        self.files.push(File {
+            id: self.next_id,
+            path: path.to_owned(),
+            bucket: bucket.to_owned(),
+            blake3_hash: blake3_hash.to_owned(),
+            size,
+            content_type: content_type.to_owned(),
+            created_at: now,
+            updated_at: now,
+        });
+        Ok(self.next_id)
+    }
+
+    fn put_upload(
+        &mut self,
+        upload: Upload,
+        path: &str,
+        bucket: &str,
+        content_type: &str,
+    ) -> Result<PutResult, Self::Err> {
+        let Some(file) = self
+            .files
+            .iter_mut()
+            .find(|f| f.bucket == bucket && f.path == path)
+        else {
+            let id = self.commit_upload(upload, path, bucket, content_type)?;
+            return Ok(PutResult { id, created: true });
+        };
+        let data = self.staged.remove(&upload.id()).ok_or_else(not_found)?;
+        file.blake3_hash = upload.hash();
+        file.size = data.len();
+        file.content_type = content_type.to_owned();
+        file.updated_at = Utc::now();
+        self.blobs.insert(upload.hash(), data);
+        Ok(PutResult {
+            id: file.id,
+            created: false,
+        })
+    }
+
+    fn abort_upload(&mut self, upload: Upload) -> Result<(), Self::Err> {
+        self.staged.remove(&upload.id());
+        Ok(())
+    }
+
+    fn delete_bucket(&mut self, bucket: &str) -> Result<DeleteResult, Self::Err> {
+        let before = self.files.len();
+        self.files.retain(|f| f.bucket != bucket);
+        Ok(DeleteResult {
+            files: before - self.files.len(),
+            blobs: 0,
+        })
+    }
+
+    fn get_buckets(
+        &mut self,
+        _modified_since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Bucket>, Self::Err> {
+        let mut buckets: Vec<Bucket> = vec![];
+        for f in &self.files {
+            match buckets.iter_mut().find(|b| b.id == f.bucket) {
+                Some(b) => {
+                    b.files_count += 1;
+                    b.total_size += f.size as i64;
+                }
+                None => buckets.push(Bucket {
+                    id: f.bucket.clone(),
+                    files_count: 1,
+                    total_size: f.size as i64,
+                    last_modified: f.updated_at,
+                }),
+            }
+        }
+        Ok(buckets)
+    }
+
+    fn get_files(&mut self, bucket: &str, query: &FileQuery) -> Result<Vec<File>, Self::Err> {
+        Ok(self
+            .files
+            .iter()
+            .filter(|f| f.bucket == bucket)
+            .filter(|f| query.prefix.as_ref().is_none_or(|p| f.path.starts_with(p)))
+            .take(query.limit.unwrap_or(usize::MAX))
+            .map(clone_file)
+            .collect())
+    }
+
+    fn get_common_prefixes(
+        &mut self,
+        bucket: &str,
+        prefix: &str,
+        delimiter: &str,
+    ) -> Result<Vec<String>, Self::Err> {
+        let mut prefixes: Vec<String> = self
+            .files
+            .iter()
+            .filter(|f| f.bucket == bucket)
+            .filter_map(|f| {
+                let rest = f.path.strip_prefix(prefix)?;
+                let level = rest.find(delimiter)? + delimiter.len();
+                Some(format!("{prefix}{}", &rest[..level]))
+            })
+            .collect();
+        prefixes.sort();
+        prefixes.dedup();
+        Ok(prefixes)
+    }
+
+    fn get_last_file(&mut self, bucket: &str) -> Result<File, Self::Err> {
+        self.files
+            .iter()
+            .rfind(|f| f.bucket == bucket)
+            .map(clone_file)
+            .ok_or_else(not_found)
+    }
+
+    fn get_file_data(
+        &self,
+        blake3_hash: &str,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> Result<usize, Self::Err> {
+        let data = self.blobs.get(blake3_hash).ok_or_else(not_found)?;
+        let offset = usize::try_from(offset)
+            .unwrap_or(usize::MAX)
+            .min(data.len());
+        let read = buf.len().min(data.len() - offset);
+        buf[..read].copy_from_slice(&data[offset..offset + read]);
+        Ok(read)
+    }
+
+    fn get_file_info(&mut self, id: i64) -> Result<File, Self::Err> {
+        self.files
+            .iter()
+            .find(|f| f.id == id)
+            .map(clone_file)
+            .ok_or_else(not_found)
+    }
+
+    fn get_blob(&mut self, blake3_hash: &str) -> Result<Blob, Self::Err> {
+        let file = self
+            .files
+            .iter()
+            .find(|f| f.blake3_hash == blake3_hash)
+            .ok_or_else(not_found)?;
+        Ok(Blob {
+            blake3_hash: file.blake3_hash.clone(),
+            size: file.size,
+            content_type: file.content_type.clone(),
+            created_at: file.created_at,
+        })
+    }
+
+    fn get_blob_files(&mut self, blake3_hash: &str) -> Result<Vec<File>, Self::Err> {
+        Ok(self
+            .files
+            .iter()
+            .filter(|f| f.blake3_hash == blake3_hash)
+            .map(clone_file)
+            .collect())
+    }
+
+    fn search_file_info(&mut self, bucket: &str, path: &str) -> Result<File, Self::Err> {
+        self.files
+            .iter()
+            .find(|f| f.bucket == bucket && f.path == path)
+            .map(clone_file)
+            .ok_or_else(not_found)
+    }
+
+    fn get_stats(&mut self, bucket: Option<&str>, largest: usize) -> Result<Stats, Self::Err> {
+        let mut files: Vec<&File> = self
+            .files
+            .iter()
+            .filter(|f| bucket.is_none_or(|b| f.bucket == b))
+            .collect();
+        let logical_size = files.iter().map(|f| f.size as i64).sum();
+        let mut hashes: Vec<&str> = files.iter().map(|f| f.blake3_hash.as_str()).collect();
+        hashes.sort_unstable();
+        hashes.dedup();
+        let physical_size = hashes
+            .iter()
+            .filter_map(|h| self.blobs.get(*h))
+            .map(|b| b.len() as i64)
+            .sum();
+        files.sort_by_key(|f| std::cmp::Reverse(f.size));
+        Ok(Stats {
+            files_count: files.len() as i64,
+            logical_size,
+            physical_size,
+            dedup_ratio: 1.0,
+            largest_files: files.into_iter().take(largest).map(clone_file).collect(),
+            database_size: 0,
+            wal_size: 0,
+        })
+    }
+
+    fn move_file(&mut self, id: i64, bucket: &str, path: &str) -> Result<Option<File>, Self::Err> {
+        if self
+            .files
+            .iter()
+            .any(|f| f.id != id && f.bucket == bucket && f.path == path)
+        {
+            return Ok(None);
+        }
+        let file = self
+            .files
+            .iter_mut()
+            .find(|f| f.id == id)
+            .ok_or_else(not_found)?;
+        bucket.clone_into(&mut file.bucket);
+        path.clone_into(&mut file.path);
+        file.updated_at = Utc::now();
+        Ok(Some(clone_file(file)))
+    }
+
+    fn copy_file(
+        &mut self,
+        id: i64,
+        bucket: &str,
+        path: &str,
+        overwrite: bool,
+    ) -> Result<Option<i64>, Self::Err> {
+        let source = self
+            .files
+            .iter()
+            .find(|f| f.id == id)
+            .map(clone_file)
+            .ok_or_else(not_found)?;
+        if let Some(existing) = self
+            .files
+            .iter()
+            .position(|f| f.bucket == bucket && f.path == path)
+        {
+            if !overwrite {
+                return Ok(None);
+            }
+            self.files.remove(existing);
+        }
+        self.link_blob(&source.blake3_hash, path, bucket, &source.content_type)
+            .map(Some)
+    }
+
+    fn copy_bucket(
+        &mut self,
+        source: &str,
+        target: &str,
+        prefix: Option<&str>,
+        overwrite: bool,
+    ) -> Result<Vec<i64>, Self::Err> {
+        let copies: Vec<(i64, String)> = self
+            .files
+            .iter()
+            .filter(|f| f.bucket == source && f.path.starts_with(prefix.unwrap_or_default()))
+            .map(|f| (f.id, f.path.clone()))
+            .collect();
+        let mut ids = vec![];
+        for (id, path) in copies {
+            if let Some(copied) = self.copy_file(id, target, &path, overwrite)? {
+                ids.push(copied);
+            }
+        }
+        Ok(ids)
+    }
+
+    fn delete_file(&mut self, id: i64) -> Result<DeleteResult, Self::Err> {
+        let before = self.files.len();
+        self.files.retain(|f| f.id != id);
+        Ok(DeleteResult {
+            files: before - self.files.len(),
+            blobs: 0,
+        })
+    }
+
+    fn set_versioning(&mut self, _bucket: &str, _enabled: bool) -> Result<(), Self::Err> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+
+    fn get_versioning(&mut self, _bucket: &str) -> Result<bool, Self::Err> {
+        Ok(false)
+    }
+
+    fn get_file_versions(&mut self, id: i64) -> Result<Vec<FileVersion>, Self::Err> {
+        let file = self.get_file_info(id)?;
+        Ok(vec![FileVersion {
+            version: 1,
+            blake3_hash: file.blake3_hash,
+            size: file.size,
+            content_type: file.content_type,
+            created_at: file.updated_at,
+            current: true,
+        }])
+    }
+
+    fn delete_file_version(&mut self, id: i64, version: i64) -> Result<DeleteResult, Self::Err> {
+        if version == 1 {
+            self.delete_file(id)
+        } else {
+            Ok(DeleteResult::default())
+        }
+    }
+
+    fn restore_file_version(&mut self, id: i64, version: i64) -> Result<FileVersion, Self::Err> {
+        self.get_file_versions(id)?
+            .into_iter()
+            .find(|v| v.version == version)
+            .ok_or_else(not_found)
+    }
+}
+
+fn clone_file(f: &File) -> File {
+    File {
+        id: f.id,
+        path: f.path.clone(),
+        bucket: f.bucket.clone(),
+        blake3_hash: f.blake3_hash.clone(),
+        size: f.size,
+        content_type: f.content_type.clone(),
+        created_at: f.created_at,
+        updated_at: f.updated_at,
+    }
+}
+
+#[tokio::test]
+async fn insert_and_get_file_content() {
+    // Arrange
+    let app = server::create_storage_routes(FakeStorage::default());
+    let insert = Request::post("/api/bucket1/file.txt")
+        .body(Body::from("content"))
+        .unwrap();
+    let response = app.clone().oneshot(insert).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    // Act
+    let get = Request::get("/api/bucket1/file.txt")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(get).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers().get("content-type").unwrap(),
+        "text/plain"
+    );
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(&body[..], b"content");
+}
+
+#[tokio::test]
+async fn get_last_file_of_unexist_bucket() {
+    // Arrange
+    let app = server::create_storage_routes(FakeStorage::default());
+
+    // Act
+    let get = Request::get("/api/bucket/bucket1/last")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(get).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,319 @@
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use axum::Router;
+use axum::body::{Body, Bytes, to_bytes};
+use axum::http::{Request, StatusCode};
+use futures::StreamExt;
+use server::domain::Storage;
+use server::filesystem::FileSystem;
+use server::sqlite::{Mode, Sqlite};
+use tower::ServiceExt;
+
+fn app(dir: &Path) -> Result<Router, Box<dyn std::error::Error>> {
+    let db = dir.join("fs.db");
+    let blobs = dir.join("blobs");
+    FileSystem::open(&db, Mode::ReadWrite, &blobs)?.new_database()?;
+    Ok(server::create_filesystem_routes(db, blobs, 2)?)
+}
+
+fn blob_files(dir: &Path) -> usize {
+    let mut count = 0;
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.ends_with("staging") {
+            continue;
+        }
+        if path.is_dir() {
+            count += blob_files(&path);
+        } else {
+            count += 1;
+        }
+    }
+    count
+}
+
+async fn insert(app: &Router, uri: &str) -> StatusCode {
+    let insert = Request::post(uri).body(Body::from("content")).unwrap();
+    app.clone().oneshot(insert).await.unwrap().status()
+}
+
+#[tokio::test]
+async fn insert_and_get_file_content() {
+    // Arrange
+    let dir = tempfile::tempdir().unwrap();
+    let app = app(dir.path()).unwrap();
+    assert_eq!(
+        insert(&app, "/api/bucket1/file.txt").await,
+        StatusCode::CREATED
+    );
+
+    // Act
+    let get = Request::get("/api/bucket1/file.txt")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(get).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(&body[..], b"content");
+    assert_eq!(blob_files(&dir.path().join("blobs")), 1);
+}
+
+#[tokio::test]
+async fn same_content_stored_once() {
+    // Arrange
+    let dir = tempfile::tempdir().unwrap();
+    let app = app(dir.path()).unwrap();
+
+    // Act
+    assert_eq!(
+        insert(&app, "/api/bucket1/f1.txt").await,
+        StatusCode::CREATED
+    );
+    assert_eq!(
+        insert(&app, "/api/bucket1/f2.txt").await,
+        StatusCode::CREATED
+    );
+
+    // Assert
+    assert_eq!(blob_files(&dir.path().join("blobs")), 1);
+}
+
+#[tokio::test]
+async fn blob_file_removed_with_last_file() {
+    // Arrange
+    let dir = tempfile::tempdir().unwrap();
+    let app = app(dir.path()).unwrap();
+    assert_eq!(
+        insert(&app, "/api/bucket1/f1.txt").await,
+        StatusCode::CREATED
+    );
+    assert_eq!(
+        insert(&app, "/api/bucket1/f2.txt").await,
+        StatusCode::CREATED
+    );
+    let delete = Request::delete("/api/bucket1/f1.txt")
+        .body(Body::empty())
+        .unwrap();
+    app.clone().oneshot(delete).await.unwrap();
+    assert_eq!(blob_files(&dir.path().join("blobs")), 1);
+
+    // Act
+    let delete = Request::delete("/api/bucket1").body(Body::empty()).unwrap();
+    let response = app.oneshot(delete).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(blob_files(&dir.path().join("blobs")), 0);
+}
+
+#[tokio::test]
+async fn replaced_blob_file_removed() {
+    // Arrange
+    let dir = tempfile::tempdir().unwrap();
+    let app = app(dir.path()).unwrap();
+    assert_eq!(
+        insert(&app, "/api/bucket1/file.txt").await,
+        StatusCode::CREATED
+    );
+
+    // Act
+    let put = Request::put("/api/bucket1/file.txt")
+        .body(Body::from("new content"))
+        .unwrap();
+    let response = app.oneshot(put).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(blob_files(&dir.path().join("blobs")), 1);
+}
+
+#[tokio::test]
+async fn abandoned_upload_removed() {
+    // Arrange
+    let dir = tempfile::tempdir().unwrap();
+    let app = app(dir.path()).unwrap();
+    let staging = dir.path().join("blobs").join("staging");
+    // Body never ends as if client stopped sending it
+    let body = futures::stream::once(async { Ok::<_, io::Error>(Bytes::from("partial")) })
+        .chain(futures::stream::pending());
+    let insert = Request::post("/api/bucket1/file.txt")
+        .body(Body::from_stream(body))
+        .unwrap();
+
+    // Act
+    let result = tokio::time::timeout(Duration::from_millis(200), app.oneshot(insert)).await;
+
+    // Assert
+    assert!(result.is_err());
+    let mut staged = usize::MAX;
+    for _ in 0..50 {
+        staged = std::fs::read_dir(&staging).unwrap().count();
+        if staged == 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(staged, 0);
+}
+
+#[test]
+fn embedded_blobs_detected() -> Result<(), Box<dyn std::error::Error>> {
+    // Arrange
+    let dir = tempfile::tempdir()?;
+    let db = dir.path().join("embedded.db");
+    let mut storage = Sqlite::open(&db, Mode::ReadWrite)?;
+    storage.new_database()?;
+    let mut upload = storage.begin_upload()?;
+    storage.append_upload(&mut upload, b"content")?;
+    storage.commit_upload(upload, "file.txt", "bucket", "text/plain")?;
+
+    // Act
+    let embedded = storage.has_embedded_blobs()?;
+
+    // Assert
+    assert!(embedded);
+    Ok(())
+}
+
+#[test]
+fn no_embedded_blobs_in_filesystem_store() -> Result<(), Box<dyn std::error::Error>> {
+    // Arrange
+    let dir = tempfile::tempdir()?;
+    let db = dir.path().join("fs.db");
+    let mut storage = FileSystem::open(&db, Mode::ReadWrite, dir.path().join("blobs"))?;
+    storage.new_database()?;
+    let mut upload = storage.begin_upload()?;
+    storage.append_upload(&mut upload, b"content")?;
+    storage.commit_upload(upload, "file.txt", "bucket", "text/plain")?;
+
+    // Act
+    let embedded = Sqlite::open(&db, Mode::ReadOnly)?.has_embedded_blobs()?;
+
+    // Assert
+    assert!(!embedded);
+    Ok(())
+}
+
+#[test]
+fn external_blobs_detected() -> Result<(), Box<dyn std::error::Error>> {
+    // Arrange
+    let dir = tempfile::tempdir()?;
+    let db = dir.path().join("fs.db");
+    let mut storage = FileSystem::open(&db, Mode::ReadWrite, dir.path().join("blobs"))?;
+    storage.new_database()?;
+    let mut upload = storage.begin_upload()?;
+    storage.append_upload(&mut upload, b"content")?;
+    storage.commit_upload(upload, "file.txt", "bucket1", "text/plain")?;
+
+    // Act
+    let external = Sqlite::open(&db, Mode::ReadOnly)?.has_external_blobs()?;
+
+    // Assert
+    assert!(external);
+    Ok(())
+}
+
+#[test]
+fn no_external_blobs_in_database_store() -> Result<(), Box<dyn std::error::Error>> {
+    // Arrange
+    let dir = tempfile::tempdir()?;
+    let db = dir.path().join("embedded.db");
+    let mut storage = Sqlite::open(&db, Mode::ReadWrite)?;
+    storage.new_database()?;
+    let mut upload = storage.begin_upload()?;
+    storage.append_upload(&mut upload, b"content")?;
+    storage.commit_upload(upload, "file.txt", "bucket1", "text/plain")?;
+    let empty = storage.begin_upload()?;
+    storage.commit_upload(empty, "empty.txt", "bucket1", "text/plain")?;
+
+    // Act
+    let external = storage.has_external_blobs()?;
+
+    // Assert
+    assert!(!external);
+    Ok(())
+}
+
+#[test]
+fn existing_buckets_detected() -> Result<(), Box<dyn std::error::Error>> {
+    // Arrange
+    let dir = tempfile::tempdir()?;
+    let db = dir.path().join("embedded.db");
+    let mut storage = Sqlite::open(&db, Mode::ReadWrite)?;
+    storage.new_database()?;
+    for bucket in ["file", "bucket1"] {
+        let upload = storage.begin_upload()?;
+        storage.commit_upload(upload, "file.txt", bucket, "text/plain")?;
+    }
+
+    // Act
+    let existing = storage.existing_buckets(&["file", "blob", "meta"])?;
+
+    // Assert
+    assert_eq!(existing, vec!["file".to_owned()]);
+    Ok(())
+}
+
+#[test]
+fn pending_uploads_removed_from_database() -> Result<(), Box<dyn std::error::Error>> {
+    // Arrange
+    let dir = tempfile::tempdir()?;
+    let db = dir.path().join("pending.db");
+    let mut storage = Sqlite::open(&db, Mode::ReadWrite)?;
+    storage.new_database()?;
+    let mut committed = storage.begin_upload()?;
+    storage.append_upload(&mut committed, b"content")?;
+    let hash = committed.hash();
+    storage.commit_upload(committed, "file.txt", "bucket1", "text/plain")?;
+    let mut pending = storage.begin_upload()?;
+    storage.append_upload(&mut pending, b"partial")?;
+
+    // Act
+    let removed = storage.remove_pending_uploads()?;
+
+    // Assert
+    assert_eq!(removed, 1);
+    assert_eq!(storage.remove_pending_uploads()?, 0);
+    let mut content = vec![0u8; 7];
+    assert_eq!(storage.get_file_data(&hash, 0, &mut content)?, 7);
+    assert_eq!(content, b"content");
+    Ok(())
+}
+
+#[test]
+fn pending_uploads_removed_from_filesystem_store() -> Result<(), Box<dyn std::error::Error>> {
+    // Arrange
+    let dir = tempfile::tempdir()?;
+    let db = dir.path().join("fs.db");
+    let blobs = dir.path().join("blobs");
+    let mut storage = FileSystem::open(&db, Mode::ReadWrite, &blobs)?;
+    storage.new_database()?;
+    let mut committed = storage.begin_upload()?;
+    storage.append_upload(&mut committed, b"content")?;
+    let hash = committed.hash();
+    storage.commit_upload(committed, "file.txt", "bucket1", "text/plain")?;
+    let mut pending = storage.begin_upload()?;
+    storage.append_upload(&mut pending, b"partial")?;
+
+    // Act
+    let removed = storage.remove_pending_uploads()?;
+
+    // Assert
+    assert_eq!(removed, 1);
+    assert!(!blobs.join("staging").exists());
+    let mut content = vec![0u8; 7];
+    assert_eq!(storage.get_file_data(&hash, 0, &mut content)?, 7);
+    assert_eq!(content, b"content");
+    // Uploads still work after staging directory is removed
+    let mut next = storage.begin_upload()?;
+    storage.append_upload(&mut next, b"next")?;
+    storage.commit_upload(next, "next.txt", "bucket1", "text/plain")?;
+    Ok(())
+}
@@ -4,9 +4,17 @@ use futures::TryStreamExt;
 use futures::channel::oneshot;
 use futures::channel::oneshot::Sender;
 use futures::future::join_all;
+use kernel::BlobLink;
 use kernel::Bucket;
+use kernel::BucketCopy;
 use kernel::DeleteResult;
+use kernel::Destination;
 use kernel::File as FileItem;
+use kernel::FileCopy;
+use kernel::Listing;
+use kernel::PutResult;
+use kernel::Stats;
+use kernel::{FileVersion, Versioning};
 use rand::RngExt;
 use reqwest::Client;
 use reqwest::StatusCode;
@@ -207,7 +215,7 @@ impl AsyncTestContext for BstoreAsyncContext {
             .unwrap();
 
         let task = tokio::spawn(async move {
-            let app = server::create_routes(cloned_db).unwrap();
+            let app = server::create_routes(cloned_db, 4).unwrap();
             axum::serve(listener, app)
                 .with_graceful_shutdown(async { recv.await.unwrap() })
                 .await
@@ -342,7 +350,7 @@ async fn insert_zip(ctx: &mut BstoreAsyncContext) {
     let client = Client::new();
     let bucket = Uuid::new_v4();
 
-    let uri = format!("http://localhost:{}/api/{bucket}/zip", ctx.port);
+    let uri = format!("http://localhost:{}/api/bucket/{bucket}/zip", ctx.port);
 
     let file = ctx.root.parent().unwrap().join("test.zip");
     let zip_file_path = file.to_str().unwrap();
@@ -376,6 +384,127 @@ async fn insert_zip(ctx: &mut BstoreAsyncContext) {
     assert_eq!(4, result.len());
 }
 
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn insert_many_existing_files_conflict(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    let uri = format!("http://localhost:{}/api/{bucket}", ctx.port);
+    let form = wrap_directory_into_multipart_form(&ctx.root).await.unwrap();
+    client.post(&uri).multipart(form).send().await.unwrap();
+    let form = wrap_directory_into_multipart_form(&ctx.root).await.unwrap();
+
+    // Act
+    let response = client.post(&uri).multipart(form).send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn insert_many_storage_failure(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    let uri = format!("http://localhost:{}/api/{bucket}", ctx.port);
+    let form = wrap_directory_into_multipart_form(&ctx.root).await.unwrap();
+    fail_file_changes(ctx, "INSERT");
+
+    // Act
+    let response = client.post(&uri).multipart(form).send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn insert_zip_corrupted_entry(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    let uri = format!("http://localhost:{}/api/bucket/{bucket}/zip", ctx.port);
+    let mut zip = zip::ZipWriter::new(io::Cursor::new(vec![]));
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    zip.start_file("f1", options).unwrap();
+    zip.write_all(b"first content").unwrap();
+    zip.start_file("f2", options).unwrap();
+    zip.write_all(b"second content").unwrap();
+    let mut archive = zip.finish().unwrap().into_inner();
+    // Damage the second entry's content so that its checksum doesn't match
+    let offset = archive
+        .windows(b"second".len())
+        .position(|w| w == b"second")
+        .unwrap();
+    archive[offset] = b'S';
+
+    // Act
+    let response = client.post(&uri).body(archive).send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    let message = response.text().await.unwrap();
+    assert!(message.starts_with("only files"));
+    assert!(message.contains("f2"));
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn insert_zip_deprecated_path(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    let uri = format!("http://localhost:{}/api/{bucket}/zip", ctx.port);
+    let mut archive = io::Cursor::new(vec![]);
+    zip_dir(ctx.root.as_path(), &mut archive).unwrap();
+
+    // Act
+    let response = client
+        .post(&uri)
+        .body(archive.into_inner())
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let inserted: Vec<i64> = response.json().await.unwrap();
+    assert_eq!(inserted.len(), 4);
+    let missing = client.get(&uri).send().await.unwrap();
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn insert_zip_existing_files_conflict(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    let uri = format!("http://localhost:{}/api/bucket/{bucket}/zip", ctx.port);
+    let mut archive = io::Cursor::new(vec![]);
+    zip_dir(ctx.root.as_path(), &mut archive).unwrap();
+    let archive = archive.into_inner();
+    client
+        .post(&uri)
+        .body(archive.clone())
+        .send()
+        .await
+        .unwrap();
+
+    // Act
+    let response = client.post(&uri).body(archive).send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+}
+
 #[test_context(BstoreAsyncContext)]
 #[tokio::test]
 #[serial]
@@ -511,7 +640,7 @@ async fn get_last_bucket_file(ctx: &mut BstoreAsyncContext) {
     let client = Client::new();
     let bucket = Uuid::new_v4();
     let uri = format!("http://localhost:{}/api/{bucket}", ctx.port);
-    let uri_get = format!("http://localhost:{}/api/{bucket}/last", ctx.port);
+    let uri_get = format!("http://localhost:{}/api/bucket/{bucket}/last", ctx.port);
 
     let form = wrap_directory_into_multipart_form(&ctx.root).await.unwrap();
 
@@ -554,6 +683,7 @@ async fn get_buckets(ctx: &mut BstoreAsyncContext) {
     match result {
         Ok(x) => {
             assert_eq!(x.len(), 1);
+            assert_eq!(x[0].total_size, 8);
         }
         Err(e) => {
             assert!(false, "get_buckets error: {e}");
@@ -835,3 +965,2176 @@ async fn search_and_delete_file_failure(ctx: &mut BstoreAsyncContext) {
         }
     }
 }
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn get_file_content_larger_than_read_chunk(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    let content: Vec<u8> = (0..1_000_003).map(|i| (i % 251) as u8).collect();
+    let uri = format!("http://localhost:{}/api/{bucket}/large", ctx.port);
+    let ids: Vec<i64> = client
+        .post(uri)
+        .body(content.clone())
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let file_uri = format!("http://localhost:{}/api/file/{}", ctx.port, ids[0]);
+
+    // Act
+    let result = client.get(file_uri).send().await.unwrap();
+
+    // Assert
+    assert_eq!(result.status(), StatusCode::OK);
+    let buffer = result.bytes().await.unwrap();
+    assert_eq!(buffer.len(), content.len());
+    assert!(buffer == content);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn insert_one_larger_than_write_chunk(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    let content: Vec<u8> = (0..2_500_000).map(|i| (i % 253) as u8).collect();
+    let uri = format!("http://localhost:{}/api/{bucket}/large", ctx.port);
+
+    // Act
+    let result = client
+        .post(&uri)
+        .body(content.clone())
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(result.status(), StatusCode::CREATED);
+    let ids: Vec<i64> = result.json().await.unwrap();
+    assert_eq!(1, ids.len());
+    let meta_uri = format!("http://localhost:{}/api/file/{}/meta", ctx.port, ids[0]);
+    let meta: FileItem = client
+        .get(meta_uri)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(meta.size, content.len());
+    let buffer = client.get(uri).send().await.unwrap().bytes().await.unwrap();
+    assert!(buffer == content);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn get_file_content_range(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    let content: Vec<u8> = (0..1_500_000).map(|i| (i % 241) as u8).collect();
+    let uri = format!("http://localhost:{}/api/{bucket}/large", ctx.port);
+    client
+        .post(&uri)
+        .body(content.clone())
+        .send()
+        .await
+        .unwrap();
+
+    // Act
+    let result = client
+        .get(&uri)
+        .header("Range", "bytes=1000000-1100000")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(result.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(
+        result.headers().get("content-range").unwrap(),
+        "bytes 1000000-1100000/1500000"
+    );
+    assert_eq!(result.headers().get("accept-ranges").unwrap(), "bytes");
+    let buffer = result.bytes().await.unwrap();
+    assert!(buffer == content[1_000_000..=1_100_000]);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn get_file_content_unsatisfiable_range(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    let uri = format!("http://localhost:{}/api/{bucket}/small", ctx.port);
+    client.post(&uri).body("content").send().await.unwrap();
+
+    // Act
+    let result = client
+        .get(&uri)
+        .header("Range", "bytes=100-")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(result.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(result.headers().get("content-range").unwrap(), "bytes */7");
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn get_file_content_not_modified(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    let uri = format!("http://localhost:{}/api/{bucket}/small", ctx.port);
+    client.post(&uri).body("content").send().await.unwrap();
+    let etag = client
+        .get(&uri)
+        .send()
+        .await
+        .unwrap()
+        .headers()
+        .get("etag")
+        .unwrap()
+        .clone();
+
+    // Act
+    let result = client
+        .get(&uri)
+        .header("If-None-Match", etag.clone())
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(result.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(result.headers().get("etag").unwrap(), etag);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn get_file_info_precondition_failed(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    let uri = format!("http://localhost:{}/api/{bucket}/small", ctx.port);
+    let ids: Vec<i64> = client
+        .post(&uri)
+        .body("content")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let meta_uri = format!("http://localhost:{}/api/file/{}/meta", ctx.port, ids[0]);
+
+    // Act
+    let result = client
+        .get(meta_uri)
+        .header("If-Match", "\"other\"")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(result.status(), StatusCode::PRECONDITION_FAILED);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn get_file_content_type(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    let declared = format!("http://localhost:{}/api/{bucket}/declared", ctx.port);
+    let by_name = format!("http://localhost:{}/api/{bucket}/page.html", ctx.port);
+    let by_content = format!("http://localhost:{}/api/{bucket}/image", ctx.port);
+    client
+        .post(&declared)
+        .header("Content-Type", "application/json")
+        .body("{}")
+        .send()
+        .await
+        .unwrap();
+    client.post(&by_name).body("<html/>").send().await.unwrap();
+    client
+        .post(&by_content)
+        .body(&b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"[..])
+        .send()
+        .await
+        .unwrap();
+
+    // Act
+    let declared = client.get(&declared).send().await.unwrap();
+    let by_name = client.get(&by_name).send().await.unwrap();
+    let by_content = client.get(&by_content).send().await.unwrap();
+
+    // Assert
+    assert_eq!(
+        declared.headers().get("content-type").unwrap(),
+        "application/json"
+    );
+    assert_eq!(by_name.headers().get("content-type").unwrap(), "text/html");
+    assert_eq!(
+        by_content.headers().get("content-type").unwrap(),
+        "image/png"
+    );
+    let info: FileItem = client
+        .get(format!(
+            "http://localhost:{}/api/bucket/{bucket}/last",
+            ctx.port
+        ))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(info.content_type, "image/png");
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn get_file_content_inline(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    let uri = format!("http://localhost:{}/api/{bucket}/dir%2Fnote.txt", ctx.port);
+    client.post(&uri).body("content").send().await.unwrap();
+
+    // Act
+    let inline = client
+        .get(format!("{uri}?disposition=inline"))
+        .send()
+        .await
+        .unwrap();
+    let attachment = client.get(&uri).send().await.unwrap();
+
+    // Assert
+    assert_eq!(inline.status(), StatusCode::OK);
+    assert_eq!(
+        inline.headers().get("content-disposition").unwrap(),
+        r#"inline; filename="note.txt""#
+    );
+    assert_eq!(
+        inline.headers().get("x-content-type-options").unwrap(),
+        "nosniff"
+    );
+    assert_eq!(
+        attachment.headers().get("content-disposition").unwrap(),
+        r#"attachment; filename="note.txt""#
+    );
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn get_file_content_inline_sandbox(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    let html = format!("http://localhost:{}/api/{bucket}/page.html", ctx.port);
+    let pdf = format!("http://localhost:{}/api/{bucket}/doc.pdf", ctx.port);
+    client
+        .post(&html)
+        .body("<script>alert(1)</script>")
+        .send()
+        .await
+        .unwrap();
+    client
+        .post(&pdf)
+        .header("content-type", "application/pdf")
+        .body("%PDF-1.7")
+        .send()
+        .await
+        .unwrap();
+
+    // Act
+    let html = client
+        .get(format!("{html}?disposition=inline"))
+        .send()
+        .await
+        .unwrap();
+    let pdf = client
+        .get(format!("{pdf}?disposition=inline"))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(
+        html.headers().get("content-security-policy").unwrap(),
+        "sandbox"
+    );
+    assert_eq!(
+        pdf.headers().get("content-type").unwrap(),
+        "application/pdf"
+    );
+    assert!(pdf.headers().get("content-security-policy").is_none());
+    assert_eq!(
+        pdf.headers().get("x-content-type-options").unwrap(),
+        "nosniff"
+    );
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn get_file_content_not_modified_since(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    let uri = format!("http://localhost:{}/api/{bucket}/small", ctx.port);
+    client.post(&uri).body("content").send().await.unwrap();
+    let result = client.get(&uri).send().await.unwrap();
+    let last_modified = result.headers().get("last-modified").unwrap().clone();
+
+    // Act
+    let not_modified = client
+        .get(&uri)
+        .header("If-Modified-Since", last_modified)
+        .send()
+        .await
+        .unwrap();
+    let modified = client
+        .get(&uri)
+        .header("If-Modified-Since", "Wed, 21 Oct 2015 07:28:00 GMT")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(not_modified.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(modified.status(), StatusCode::OK);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn get_bucket_files_modified_since(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    let uri = format!("http://localhost:{}/api/{bucket}", ctx.port);
+    let buckets_uri = format!("http://localhost:{}/api/", ctx.port);
+    let form = wrap_directory_into_multipart_form(&ctx.root).await.unwrap();
+    client.post(&uri).multipart(form).send().await.unwrap();
+
+    // Act
+    let past: Vec<FileItem> = client
+        .get(format!("{uri}?modified_since=2015-10-21T07:28:00Z"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let future = client
+        .get(format!("{uri}?modified_since=2100-01-01T00:00:00Z"))
+        .send()
+        .await
+        .unwrap();
+    let buckets: Vec<Bucket> = client
+        .get(format!("{buckets_uri}?modified_since=2100-01-01T00:00:00Z"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(past.len(), 4);
+    assert!(past.iter().all(|f| f.created_at == f.updated_at));
+    assert_eq!(future.status(), StatusCode::OK);
+    let future: Vec<FileItem> = future.json().await.unwrap();
+    assert!(future.is_empty());
+    assert!(buckets.is_empty());
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn put_file_created(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    let uri = format!("http://localhost:{}/api/{bucket}/file.txt", ctx.port);
+
+    // Act
+    let response = client.put(&uri).body("content").send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let result: PutResult = response.json().await.unwrap();
+    assert!(result.created);
+    let content = client.get(&uri).send().await.unwrap().text().await.unwrap();
+    assert_eq!(content, "content");
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn put_file_replaced(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    let uri = format!("http://localhost:{}/api/{bucket}/file.txt", ctx.port);
+    let inserted: Vec<i64> = client
+        .post(&uri)
+        .body("old content")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    // Act
+    let response = client.put(&uri).body("new content").send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    let result: PutResult = response.json().await.unwrap();
+    assert!(!result.created);
+    assert_eq!(result.id, inserted[0]);
+    let content = client.get(&uri).send().await.unwrap().text().await.unwrap();
+    assert_eq!(content, "new content");
+    // Bucket isn't versioned so content is replaced without new version
+    let versions: Vec<FileVersion> = client
+        .get(format!(
+            "http://localhost:{}/api/file/{}/versions",
+            ctx.port, result.id
+        ))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(versions.len(), 1);
+    assert_eq!(versions[0].version, 1);
+    // Old content isn't used anymore so only the new one's blob is removed with the file
+    let deleted: DeleteResult = client
+        .delete(&uri)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(deleted.files, 1);
+    assert_eq!(deleted.blobs, 1);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn insert_existing_file_conflict(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    let uri = format!("http://localhost:{}/api/{bucket}/file.txt", ctx.port);
+    client.post(&uri).body("old content").send().await.unwrap();
+
+    // Act
+    let response = client.post(&uri).body("new content").send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let content = client.get(&uri).send().await.unwrap().text().await.unwrap();
+    assert_eq!(content, "old content");
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn versioned_bucket_keeps_versions(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    let uri = format!("http://localhost:{}/api/{bucket}/file.txt", ctx.port);
+    let versioning: Versioning = client
+        .put(format!(
+            "http://localhost:{}/api/bucket/{bucket}/versioning",
+            ctx.port
+        ))
+        .json(&Versioning { enabled: true })
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(versioning.enabled);
+    client.post(&uri).body("v1").send().await.unwrap();
+
+    // Act
+    let inserted: Vec<i64> = client
+        .post(&uri)
+        .body("v2")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(inserted.len(), 1);
+    let versions_uri = format!(
+        "http://localhost:{}/api/file/{}/versions",
+        ctx.port, inserted[0]
+    );
+    let versions: Vec<FileVersion> = client
+        .get(&versions_uri)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(versions.len(), 2);
+    assert_eq!(versions[0].version, 2);
+    assert!(versions[0].current);
+    assert!(!versions[1].current);
+    let current = client.get(&uri).send().await.unwrap().text().await.unwrap();
+    assert_eq!(current, "v2");
+    let previous = client
+        .get(format!("{versions_uri}/1"))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert_eq!(previous, "v1");
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn restore_file_version(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    let uri = format!("http://localhost:{}/api/{bucket}/file.txt", ctx.port);
+    client
+        .put(format!(
+            "http://localhost:{}/api/bucket/{bucket}/versioning",
+            ctx.port
+        ))
+        .json(&Versioning { enabled: true })
+        .send()
+        .await
+        .unwrap();
+    let put: PutResult = client
+        .put(&uri)
+        .body("v1")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    client.put(&uri).body("v2").send().await.unwrap();
+    let versions_uri = format!("http://localhost:{}/api/file/{}/versions", ctx.port, put.id);
+
+    // Act
+    let response = client
+        .post(format!("{versions_uri}/1/restore"))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    let restored: FileVersion = response.json().await.unwrap();
+    assert_eq!(restored.version, 3);
+    let current = client.get(&uri).send().await.unwrap().text().await.unwrap();
+    assert_eq!(current, "v1");
+    let missing = client
+        .post(format!("{versions_uri}/10/restore"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn delete_current_file_version(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    let uri = format!("http://localhost:{}/api/{bucket}/file.txt", ctx.port);
+    client
+        .put(format!(
+            "http://localhost:{}/api/bucket/{bucket}/versioning",
+            ctx.port
+        ))
+        .json(&Versioning { enabled: true })
+        .send()
+        .await
+        .unwrap();
+    let put: PutResult = client
+        .put(&uri)
+        .body("v1")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    client.put(&uri).body("v2").send().await.unwrap();
+    let versions_uri = format!("http://localhost:{}/api/file/{}/versions", ctx.port, put.id);
+
+    // Act
+    let deleted: DeleteResult = client
+        .delete(format!("{versions_uri}/2"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(deleted.files, 1);
+    assert_eq!(deleted.blobs, 1);
+    let current = client.get(&uri).send().await.unwrap().text().await.unwrap();
+    assert_eq!(current, "v1");
+    let last: DeleteResult = client
+        .delete(format!("{versions_uri}/1"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(last.files, 1);
+    let response = client.get(&uri).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn deleted_version_number_not_reused(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    let uri = format!("http://localhost:{}/api/{bucket}/file.txt", ctx.port);
+    client
+        .put(format!(
+            "http://localhost:{}/api/bucket/{bucket}/versioning",
+            ctx.port
+        ))
+        .json(&Versioning { enabled: true })
+        .send()
+        .await
+        .unwrap();
+    let put: PutResult = client
+        .put(&uri)
+        .body("v1")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    client.put(&uri).body("v2").send().await.unwrap();
+    let versions_uri = format!("http://localhost:{}/api/file/{}/versions", ctx.port, put.id);
+    client
+        .delete(format!("{versions_uri}/2"))
+        .send()
+        .await
+        .unwrap();
+
+    // Act
+    client.put(&uri).body("v3").send().await.unwrap();
+
+    // Assert
+    let versions: Vec<FileVersion> = client
+        .get(&versions_uri)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let numbers: Vec<i64> = versions.iter().map(|v| v.version).collect();
+    assert_eq!(numbers, vec![3, 1]);
+    let deleted = client
+        .get(format!("{versions_uri}/2"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(deleted.status(), StatusCode::NOT_FOUND);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn delete_file_version_storage_failure(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    let id = insert_content(ctx, bucket, "file.txt", "v1").await;
+    fail_file_changes(ctx, "DELETE");
+
+    // Act
+    let response = client
+        .delete(format!(
+            "http://localhost:{}/api/file/{id}/versions/1",
+            ctx.port
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    let uri = format!("http://localhost:{}/api/{bucket}/file.txt", ctx.port);
+    let content = client.get(&uri).send().await.unwrap().text().await.unwrap();
+    assert_eq!(content, "v1");
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn get_zip_round_trip(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    let copy = Uuid::new_v4();
+    let uri = format!("http://localhost:{}/api/{bucket}", ctx.port);
+    let form = wrap_directory_into_multipart_form(&ctx.root).await.unwrap();
+    client.post(&uri).multipart(form).send().await.unwrap();
+
+    // Act
+    let zip_uri = format!("http://localhost:{}/api/bucket/{bucket}/zip", ctx.port);
+    let response = client.get(zip_uri).send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers().get("content-type").unwrap(),
+        "application/zip"
+    );
+    let archive = response.bytes().await.unwrap();
+    let mut zip = zip::ZipArchive::new(io::Cursor::new(archive.clone())).unwrap();
+    assert_eq!(zip.len(), 4);
+    let mut content = String::new();
+    zip.by_name("d1/f1")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, "f3");
+    let inserted: Vec<i64> = client
+        .post(format!(
+            "http://localhost:{}/api/bucket/{copy}/zip",
+            ctx.port
+        ))
+        .body(archive)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(inserted.len(), 4);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn get_zip_with_prefix(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    let uri = format!("http://localhost:{}/api/{bucket}", ctx.port);
+    let form = wrap_directory_into_multipart_form(&ctx.root).await.unwrap();
+    client.post(&uri).multipart(form).send().await.unwrap();
+    let zip_uri = format!("http://localhost:{}/api/bucket/{bucket}/zip", ctx.port);
+
+    // Act
+    let response = client
+        .get(format!("{zip_uri}?prefix=d1/"))
+        .send()
+        .await
+        .unwrap();
+    let missing = client
+        .get(format!("{zip_uri}?prefix=d3/"))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    let archive = response.bytes().await.unwrap();
+    let zip = zip::ZipArchive::new(io::Cursor::new(archive)).unwrap();
+    assert_eq!(zip.len(), 1);
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn get_zip_storage_failure(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let bucket = Uuid::new_v4();
+    insert_content(ctx, bucket, "f1", "f1").await;
+    let conn = rusqlite::Connection::open(&ctx.db).unwrap();
+    conn.execute_batch("ALTER TABLE file RENAME TO file_broken")
+        .unwrap();
+    let uri = format!("http://localhost:{}/api/bucket/{bucket}/zip", ctx.port);
+
+    // Act
+    let response = reqwest::get(uri).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+async fn tar_dir(root: &Path) -> Vec<u8> {
+    let mut builder = tokio_tar::Builder::new(vec![]);
+    builder.append_dir_all(".", root).await.unwrap();
+    builder.into_inner().await.unwrap()
+}
+
+async fn insert_tar(ctx: &BstoreAsyncContext, archive: Vec<u8>) -> (StatusCode, Vec<i64>) {
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    let uri = format!("http://localhost:{}/api/bucket/{bucket}/tar", ctx.port);
+    let response = client.post(uri).body(archive).send().await.unwrap();
+    let status = response.status();
+    let inserted: Vec<i64> = response.json().await.unwrap();
+    let files: Vec<FileItem> = client
+        .get(format!("http://localhost:{}/api/{bucket}", ctx.port))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(files.iter().any(|f| f.path == "d1/f1"));
+    (status, inserted)
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn insert_tar_plain(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let archive = tar_dir(&ctx.root).await;
+
+    // Act
+    let (status, inserted) = insert_tar(ctx, archive).await;
+
+    // Assert
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(inserted.len(), 4);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn insert_tar_gzip(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let mut encoder = async_compression::tokio::write::GzipEncoder::new(vec![]);
+    encoder.write_all(&tar_dir(&ctx.root).await).await.unwrap();
+    encoder.shutdown().await.unwrap();
+
+    // Act
+    let (status, inserted) = insert_tar(ctx, encoder.into_inner()).await;
+
+    // Assert
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(inserted.len(), 4);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn insert_tar_zstd(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let mut encoder = async_compression::tokio::write::ZstdEncoder::new(vec![]);
+    encoder.write_all(&tar_dir(&ctx.root).await).await.unwrap();
+    encoder.shutdown().await.unwrap();
+
+    // Act
+    let (status, inserted) = insert_tar(ctx, encoder.into_inner()).await;
+
+    // Assert
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(inserted.len(), 4);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn insert_tar_zstd_multiple_frames(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let tar = tar_dir(&ctx.root).await;
+    let (first, second) = tar.split_at(tar.len() / 2);
+    let mut archive = vec![];
+    for part in [first, second] {
+        let mut encoder = async_compression::tokio::write::ZstdEncoder::new(vec![]);
+        encoder.write_all(part).await.unwrap();
+        encoder.shutdown().await.unwrap();
+        archive.extend(encoder.into_inner());
+    }
+
+    // Act
+    let (status, inserted) = insert_tar(ctx, archive).await;
+
+    // Assert
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(inserted.len(), 4);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn insert_tar_existing_files_conflict(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    let uri = format!("http://localhost:{}/api/bucket/{bucket}/tar", ctx.port);
+    let archive = tar_dir(&ctx.root).await;
+    client
+        .post(&uri)
+        .body(archive.clone())
+        .send()
+        .await
+        .unwrap();
+
+    // Act
+    let response = client.post(&uri).body(archive).send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn insert_tar_invalid(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    let uri = format!("http://localhost:{}/api/bucket/{bucket}/tar", ctx.port);
+
+    // Act
+    let response = client.post(uri).body(vec![1u8; 1024]).send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn insert_tar_truncated(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    let uri = format!("http://localhost:{}/api/bucket/{bucket}/tar", ctx.port);
+    let mut builder = tokio_tar::Builder::new(vec![]);
+    for (path, size) in [("f1", 10), ("f2", 10_000)] {
+        let mut header = tokio_tar::Header::new_gnu();
+        header.set_size(size);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, path, &vec![1u8; size as usize][..])
+            .await
+            .unwrap();
+    }
+    let mut archive = builder.into_inner().await.unwrap();
+    // Cut in the middle of the second file's content
+    archive.truncate(3 * 512 + 5_000);
+
+    // Act
+    let response = client.post(uri).body(archive).send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    let truncated = client
+        .get(format!("http://localhost:{}/api/{bucket}/f2", ctx.port))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(truncated.status(), StatusCode::NOT_FOUND);
+}
+
+async fn insert_content(ctx: &BstoreAsyncContext, bucket: Uuid, path: &str, content: &str) -> i64 {
+    let client = Client::new();
+    let uri = format!("http://localhost:{}/api/{bucket}/{path}", ctx.port);
+    let inserted: Vec<i64> = client
+        .post(uri)
+        .body(content.to_string())
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    inserted[0]
+}
+
+async fn content_hash(ctx: &BstoreAsyncContext, id: i64) -> String {
+    let uri = format!("http://localhost:{}/api/file/{id}/meta", ctx.port);
+    let meta: FileItem = reqwest::get(uri).await.unwrap().json().await.unwrap();
+    meta.blake3_hash
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn get_blob_content(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let bucket = Uuid::new_v4();
+    let id = insert_content(ctx, bucket, "file.txt", "blob content").await;
+    let hash = content_hash(ctx, id).await;
+    let uri = format!("http://localhost:{}/api/blob/{hash}", ctx.port);
+
+    // Act
+    let response = reqwest::get(uri).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.text().await.unwrap(), "blob content");
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn head_blob_content(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    let id = insert_content(ctx, bucket, "file.txt", "blob content").await;
+    let hash = content_hash(ctx, id).await;
+    let existing = format!("http://localhost:{}/api/blob/{hash}", ctx.port);
+    let unknown = format!("http://localhost:{}/api/blob/{}", ctx.port, "0".repeat(64));
+
+    // Act
+    let existing = client.head(existing).send().await.unwrap();
+    let unknown = client.head(unknown).send().await.unwrap();
+
+    // Assert
+    assert_eq!(existing.status(), StatusCode::OK);
+    assert_eq!(
+        existing
+            .headers()
+            .get(reqwest::header::CONTENT_LENGTH)
+            .unwrap(),
+        "12"
+    );
+    assert_eq!(unknown.status(), StatusCode::NOT_FOUND);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn get_blob_files(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let bucket1 = Uuid::new_v4();
+    let bucket2 = Uuid::new_v4();
+    let id = insert_content(ctx, bucket1, "f1.txt", "shared content").await;
+    insert_content(ctx, bucket2, "f2.txt", "shared content").await;
+    insert_content(ctx, bucket2, "f3.txt", "other content").await;
+    let hash = content_hash(ctx, id).await;
+    let uri = format!("http://localhost:{}/api/blob/{hash}/files", ctx.port);
+
+    // Act
+    let response = reqwest::get(uri).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    let files: Vec<FileItem> = response.json().await.unwrap();
+    let mut locations: Vec<(String, String)> =
+        files.into_iter().map(|f| (f.bucket, f.path)).collect();
+    locations.sort();
+    let mut expected = vec![
+        (bucket1.to_string(), "f1.txt".to_string()),
+        (bucket2.to_string(), "f2.txt".to_string()),
+    ];
+    expected.sort();
+    assert_eq!(locations, expected);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn link_file_stored_content(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let id = insert_content(ctx, Uuid::new_v4(), "f1.txt", "linked content").await;
+    let hash = content_hash(ctx, id).await;
+    let bucket = Uuid::new_v4();
+    let uri = format!("http://localhost:{}/api/{bucket}/f2.txt", ctx.port);
+    let link = BlobLink {
+        bucket: bucket.to_string(),
+        path: "f2.txt".to_string(),
+        content_type: None,
+    };
+
+    // Act
+    let response = client
+        .post(format!(
+            "http://localhost:{}/api/blob/{hash}/files",
+            ctx.port
+        ))
+        .json(&link)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let linked: Vec<i64> = response.json().await.unwrap();
+    assert_ne!(linked[0], id);
+    assert_eq!(content_hash(ctx, linked[0]).await, hash);
+    let content = client.get(&uri).send().await.unwrap();
+    assert_eq!(
+        content
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .unwrap(),
+        "text/plain"
+    );
+    assert_eq!(content.text().await.unwrap(), "linked content");
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn link_file_unknown_content(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    let uri = format!(
+        "http://localhost:{}/api/blob/{}/files",
+        ctx.port,
+        "0".repeat(64)
+    );
+    let link = BlobLink {
+        bucket: bucket.to_string(),
+        path: "f1.txt".to_string(),
+        content_type: None,
+    };
+
+    // Act
+    let response = client.post(uri).json(&link).send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let files: Vec<FileItem> = client
+        .get(format!("http://localhost:{}/api/{bucket}", ctx.port))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(files.is_empty());
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn link_file_existing_path(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    let id = insert_content(ctx, bucket, "f1.txt", "linked content").await;
+    insert_content(ctx, bucket, "f2.txt", "existing content").await;
+    let hash = content_hash(ctx, id).await;
+    let link = BlobLink {
+        bucket: bucket.to_string(),
+        path: "f2.txt".to_string(),
+        content_type: None,
+    };
+
+    // Act
+    let response = client
+        .post(format!(
+            "http://localhost:{}/api/blob/{hash}/files",
+            ctx.port
+        ))
+        .json(&link)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let uri = format!("http://localhost:{}/api/{bucket}/f2.txt", ctx.port);
+    let content = client.get(&uri).send().await.unwrap().text().await.unwrap();
+    assert_eq!(content, "existing content");
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn get_stats(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let bucket1 = Uuid::new_v4();
+    let bucket2 = Uuid::new_v4();
+    insert_content(ctx, bucket1, "f1.txt", "shared").await;
+    insert_content(ctx, bucket2, "f1.txt", "shared").await;
+    insert_content(ctx, bucket2, "f2.txt", "unique content").await;
+    let uri = format!("http://localhost:{}/api/stats?largest=1", ctx.port);
+
+    // Act
+    let response = reqwest::get(uri).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    let stats: Stats = response.json().await.unwrap();
+    assert_eq!(stats.files_count, 3);
+    assert_eq!(stats.logical_size, 26);
+    assert_eq!(stats.physical_size, 20);
+    assert!((stats.dedup_ratio - 1.3).abs() < f64::EPSILON);
+    assert_eq!(stats.largest_files.len(), 1);
+    assert_eq!(stats.largest_files[0].path, "f2.txt");
+    assert!(stats.database_size > 0);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn get_bucket_stats(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let bucket = Uuid::new_v4();
+    insert_content(ctx, bucket, "f1.txt", "shared").await;
+    insert_content(ctx, bucket, "f2.txt", "shared").await;
+    insert_content(ctx, Uuid::new_v4(), "f3.txt", "other bucket").await;
+    let uri = format!("http://localhost:{}/api/bucket/{bucket}/stats", ctx.port);
+
+    // Act
+    let response = reqwest::get(uri).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    let stats: Stats = response.json().await.unwrap();
+    assert_eq!(stats.files_count, 2);
+    assert_eq!(stats.logical_size, 12);
+    assert_eq!(stats.physical_size, 6);
+    assert!((stats.dedup_ratio - 2.0).abs() < f64::EPSILON);
+    assert_eq!(stats.largest_files.len(), 2);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn get_versioned_bucket_stats(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    client
+        .put(format!(
+            "http://localhost:{}/api/bucket/{bucket}/versioning",
+            ctx.port
+        ))
+        .json(&Versioning { enabled: true })
+        .send()
+        .await
+        .unwrap();
+    insert_content(ctx, bucket, "f1.txt", "shared").await;
+    insert_content(ctx, bucket, "f1.txt", "v2 content").await;
+    insert_content(ctx, bucket, "f2.txt", "shared").await;
+    let uri = format!("http://localhost:{}/api/bucket/{bucket}/stats", ctx.port);
+
+    // Act
+    let response = reqwest::get(uri).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    let stats: Stats = response.json().await.unwrap();
+    assert_eq!(stats.files_count, 2);
+    assert_eq!(stats.logical_size, 22);
+    assert_eq!(stats.physical_size, 16);
+    assert!((stats.dedup_ratio - 1.375).abs() < f64::EPSILON);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn get_bucket_stats_not_found(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let uri = format!(
+        "http://localhost:{}/api/bucket/{}/stats",
+        ctx.port,
+        Uuid::new_v4()
+    );
+
+    // Act
+    let response = reqwest::get(uri).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+async fn list_page(
+    ctx: &BstoreAsyncContext,
+    bucket: Uuid,
+    query: &str,
+) -> (StatusCode, Vec<String>, Option<String>) {
+    let uri = format!("http://localhost:{}/api/{bucket}?{query}", ctx.port);
+    let response = reqwest::get(uri).await.unwrap();
+    let status = response.status();
+    let token = response
+        .headers()
+        .get("x-continuation-token")
+        .map(|v| v.to_str().unwrap().to_owned());
+    let files: Vec<FileItem> = response.json().await.unwrap_or_default();
+    (status, files.into_iter().map(|f| f.path).collect(), token)
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn get_files_paginated(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let bucket = Uuid::new_v4();
+    for path in ["c.txt", "a.txt", "e.txt", "b.txt", "d.txt"] {
+        insert_content(ctx, bucket, path, path).await;
+    }
+
+    // Act
+    let (_, page1, token1) = list_page(ctx, bucket, "limit=2").await;
+    let query = format!("limit=2&cursor={}", token1.clone().unwrap());
+    let (_, page2, token2) = list_page(ctx, bucket, &query).await;
+    let query = format!("limit=2&cursor={}", token2.clone().unwrap());
+    let (_, page3, token3) = list_page(ctx, bucket, &query).await;
+
+    // Assert
+    assert_eq!(page1, vec!["a.txt", "b.txt"]);
+    assert_eq!(page2, vec!["c.txt", "d.txt"]);
+    assert_eq!(page3, vec!["e.txt"]);
+    assert!(token3.is_none());
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn get_files_sorted_by_size(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let bucket = Uuid::new_v4();
+    insert_content(ctx, bucket, "large.txt", "large content").await;
+    insert_content(ctx, bucket, "small.txt", "s").await;
+    insert_content(ctx, bucket, "medium.txt", "medium").await;
+
+    // Act
+    let (_, page1, token) = list_page(ctx, bucket, "sort=size&limit=2").await;
+    let query = format!("sort=size&limit=2&cursor={}", token.unwrap());
+    let (_, page2, _) = list_page(ctx, bucket, &query).await;
+
+    // Assert
+    assert_eq!(page1, vec!["small.txt", "medium.txt"]);
+    assert_eq!(page2, vec!["large.txt"]);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn get_files_filtered(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let bucket = Uuid::new_v4();
+    insert_content(ctx, bucket, "a.txt", "1").await;
+    insert_content(ctx, bucket, "a.json", "12345").await;
+    insert_content(ctx, bucket, "b.txt", "1234567890").await;
+
+    // Act
+    let (_, prefixed, _) = list_page(ctx, bucket, "prefix=a.").await;
+    let (_, globbed, _) = list_page(ctx, bucket, "glob=*.txt").await;
+    let (_, sized, _) = list_page(ctx, bucket, "min_size=2&max_size=9").await;
+    let (status, none, _) = list_page(ctx, bucket, "prefix=c").await;
+
+    // Assert
+    assert_eq!(prefixed, vec!["a.json", "a.txt"]);
+    assert_eq!(globbed, vec!["a.txt", "b.txt"]);
+    assert_eq!(sized, vec!["a.json"]);
+    assert_eq!(status, StatusCode::OK);
+    assert!(none.is_empty());
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn get_files_invalid_cursor(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let bucket = Uuid::new_v4();
+    insert_content(ctx, bucket, "a.txt", "a").await;
+    insert_content(ctx, bucket, "b.txt", "b").await;
+    let (_, _, token) = list_page(ctx, bucket, "limit=1").await;
+
+    // Act
+    let (invalid, _, _) = list_page(ctx, bucket, "cursor=zz").await;
+    let query = format!("sort=size&cursor={}", token.unwrap());
+    let (other_sort, _, _) = list_page(ctx, bucket, &query).await;
+
+    // Assert
+    assert_eq!(invalid, StatusCode::BAD_REQUEST);
+    assert_eq!(other_sort, StatusCode::BAD_REQUEST);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn get_files_zero_limit(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let bucket = Uuid::new_v4();
+    insert_content(ctx, bucket, "a.txt", "a").await;
+
+    // Act
+    let (status, files, token) = list_page(ctx, bucket, "limit=0").await;
+
+    // Assert
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(files.is_empty());
+    assert!(token.is_none());
+}
+
+async fn insert_tree(ctx: &BstoreAsyncContext, bucket: Uuid) {
+    let paths = [
+        "readme.txt",
+        "release/notes.txt",
+        "release/1.2/linux/app",
+        "release/1.2/windows/app.exe",
+        "release/1.3/linux/app",
+    ];
+    let form = paths
+        .iter()
+        .fold(reqwest::multipart::Form::new(), |form, path| {
+            form.part(
+                *path,
+                reqwest::multipart::Part::text(*path).file_name(*path),
+            )
+        });
+    let uri = format!("http://localhost:{}/api/{bucket}", ctx.port);
+    Client::new()
+        .post(uri)
+        .multipart(form)
+        .send()
+        .await
+        .unwrap();
+}
+
+async fn list_directory(ctx: &BstoreAsyncContext, bucket: Uuid, query: &str) -> Listing {
+    let uri = format!(
+        "http://localhost:{}/api/{bucket}?delimiter=/{query}",
+        ctx.port
+    );
+    reqwest::get(uri).await.unwrap().json().await.unwrap()
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn get_files_delimited_root(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let bucket = Uuid::new_v4();
+    insert_tree(ctx, bucket).await;
+
+    // Act
+    let listing = list_directory(ctx, bucket, "").await;
+
+    // Assert
+    assert_eq!(listing.common_prefixes, vec!["release/"]);
+    let files: Vec<String> = listing.files.into_iter().map(|f| f.path).collect();
+    assert_eq!(files, vec!["readme.txt"]);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn get_files_delimited_directory(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let bucket = Uuid::new_v4();
+    insert_tree(ctx, bucket).await;
+
+    // Act
+    let release = list_directory(ctx, bucket, "&prefix=release/").await;
+    let version = list_directory(ctx, bucket, "&prefix=release/1.2/").await;
+
+    // Assert
+    assert_eq!(
+        release.common_prefixes,
+        vec!["release/1.2/", "release/1.3/"]
+    );
+    let files: Vec<String> = release.files.into_iter().map(|f| f.path).collect();
+    assert_eq!(files, vec!["release/notes.txt"]);
+    assert_eq!(
+        version.common_prefixes,
+        vec!["release/1.2/linux/", "release/1.2/windows/"]
+    );
+    assert!(version.files.is_empty());
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn multi_segment_path(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+    let uri = format!(
+        "http://localhost:{}/api/{bucket}/dir/sub/file.txt",
+        ctx.port
+    );
+    let meta_uri = format!(
+        "http://localhost:{}/api/meta/{bucket}/dir/sub/file.txt",
+        ctx.port
+    );
+
+    // Act
+    let inserted: Vec<i64> = client
+        .post(&uri)
+        .body("nested")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    // Assert
+    let content = client.get(&uri).send().await.unwrap().text().await.unwrap();
+    assert_eq!(content, "nested");
+    let meta: FileItem = client
+        .get(&meta_uri)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(meta.id, inserted[0]);
+    assert_eq!(meta.path, "dir/sub/file.txt");
+    let deleted: DeleteResult = client
+        .delete(&uri)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(deleted.files, 1);
+    let missing = client.get(&meta_uri).send().await.unwrap();
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn bucket_operation_names_as_file_paths(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let client = Client::new();
+    let bucket = Uuid::new_v4();
+
+    // Act
+    for path in ["zip", "last", "tar", "stats", "copy", "versioning"] {
+        let uri = format!("http://localhost:{}/api/{bucket}/{path}", ctx.port);
+        // Posting to zip path adds zip archive for existing clients
+        let request = if path == "zip" {
+            client.put(&uri)
+        } else {
+            client.post(&uri)
+        };
+        request.body(path).send().await.unwrap();
+    }
+
+    // Assert
+    for path in ["zip", "last", "tar", "stats", "copy", "versioning"] {
+        let uri = format!("http://localhost:{}/api/{bucket}/{path}", ctx.port);
+        let content = client.get(&uri).send().await.unwrap().text().await.unwrap();
+        assert_eq!(content, path);
+    }
+}
+
+async fn move_file(
+    ctx: &BstoreAsyncContext,
+    id: i64,
+    destination: &Destination,
+) -> reqwest::Response {
+    let client = Client::new();
+    let uri = format!("http://localhost:{}/api/file/{id}/move", ctx.port);
+    client.post(uri).json(destination).send().await.unwrap()
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn move_file_renamed(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let bucket = Uuid::new_v4();
+    let id = insert_content(ctx, bucket, "f1", "content").await;
+    let destination = Destination {
+        bucket: None,
+        path: "d1/f2".to_string(),
+    };
+
+    // Act
+    let response = move_file(ctx, id, &destination).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    let moved: FileItem = response.json().await.unwrap();
+    assert_eq!(moved.id, id);
+    assert_eq!(moved.path, "d1/f2");
+    let uri = format!("http://localhost:{}/api/{bucket}/d1/f2", ctx.port);
+    let content = reqwest::get(uri).await.unwrap().text().await.unwrap();
+    assert_eq!(content, "content");
+    let uri = format!("http://localhost:{}/api/{bucket}/f1", ctx.port);
+    let old = reqwest::get(uri).await.unwrap();
+    assert_eq!(old.status(), StatusCode::NOT_FOUND);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn move_file_to_another_bucket(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let bucket = Uuid::new_v4();
+    let target = Uuid::new_v4();
+    let id = insert_content(ctx, bucket, "f1", "content").await;
+    let destination = Destination {
+        bucket: Some(target.to_string()),
+        path: "f1".to_string(),
+    };
+
+    // Act
+    let response = move_file(ctx, id, &destination).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    let moved: FileItem = response.json().await.unwrap();
+    assert_eq!(moved.bucket, target.to_string());
+    let uri = format!("http://localhost:{}/api/{target}/f1", ctx.port);
+    let content = reqwest::get(uri).await.unwrap().text().await.unwrap();
+    assert_eq!(content, "content");
+    let uri = format!("http://localhost:{}/api/{bucket}", ctx.port);
+    let old = reqwest::get(uri).await.unwrap();
+    assert_eq!(old.status(), StatusCode::NOT_FOUND);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn move_file_conflict(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let bucket = Uuid::new_v4();
+    let id = insert_content(ctx, bucket, "f1", "content").await;
+    insert_content(ctx, bucket, "f2", "other").await;
+    let destination = Destination {
+        bucket: None,
+        path: "f2".to_string(),
+    };
+
+    // Act
+    let response = move_file(ctx, id, &destination).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let uri = format!("http://localhost:{}/api/{bucket}/f2", ctx.port);
+    let content = reqwest::get(uri).await.unwrap().text().await.unwrap();
+    assert_eq!(content, "other");
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn move_file_empty_path(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let bucket = Uuid::new_v4();
+    let id = insert_content(ctx, bucket, "f1", "content").await;
+    let destination = Destination {
+        bucket: None,
+        path: " ".to_string(),
+    };
+
+    // Act
+    let response = move_file(ctx, id, &destination).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let uri = format!("http://localhost:{}/api/{bucket}/f1", ctx.port);
+    let content = reqwest::get(uri).await.unwrap().text().await.unwrap();
+    assert_eq!(content, "content");
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn move_file_not_found(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let destination = Destination {
+        bucket: None,
+        path: "f1".to_string(),
+    };
+
+    // Act
+    let response = move_file(ctx, 100_000, &destination).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn move_file_storage_failure(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let bucket = Uuid::new_v4();
+    let id = insert_content(ctx, bucket, "f1", "content").await;
+    fail_file_changes(ctx, "UPDATE");
+    let destination = Destination {
+        bucket: None,
+        path: "f2".to_string(),
+    };
+
+    // Act
+    let response = move_file(ctx, id, &destination).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    let uri = format!("http://localhost:{}/api/{bucket}/f1", ctx.port);
+    let content = reqwest::get(uri).await.unwrap().text().await.unwrap();
+    assert_eq!(content, "content");
+}
+
+/// Makes database reject file changes of the kind specified e.g. `UPDATE`
+/// to check how server's own failures are reported
+fn fail_file_changes(ctx: &BstoreAsyncContext, event: &str) {
+    let conn = rusqlite::Connection::open(&ctx.db).unwrap();
+    conn.execute_batch(&format!(
+        "CREATE TRIGGER fail_file_{event} BEFORE {event} ON file \
+         BEGIN SELECT RAISE(ABORT, 'storage failure'); END"
+    ))
+    .unwrap();
+}
+
+async fn copy_file(ctx: &BstoreAsyncContext, id: i64, copy: &FileCopy) -> reqwest::Response {
+    let client = Client::new();
+    let uri = format!("http://localhost:{}/api/file/{id}/copy", ctx.port);
+    client.post(uri).json(copy).send().await.unwrap()
+}
+
+async fn copy_bucket(
+    ctx: &BstoreAsyncContext,
+    bucket: Uuid,
+    copy: &BucketCopy,
+) -> reqwest::Response {
+    let client = Client::new();
+    let uri = format!("http://localhost:{}/api/bucket/{bucket}/copy", ctx.port);
+    client.post(uri).json(copy).send().await.unwrap()
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn copy_file_storage_failure(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let bucket = Uuid::new_v4();
+    let id = insert_content(ctx, bucket, "f1", "content").await;
+    fail_file_changes(ctx, "INSERT");
+    let copy = FileCopy {
+        bucket: None,
+        path: "f2".to_string(),
+        overwrite: false,
+    };
+
+    // Act
+    let response = copy_file(ctx, id, &copy).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    let uri = format!("http://localhost:{}/api/{bucket}/f2", ctx.port);
+    let missing = reqwest::get(uri).await.unwrap();
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn copy_bucket_storage_failure(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let bucket = Uuid::new_v4();
+    let target = Uuid::new_v4();
+    insert_content(ctx, bucket, "f1", "content").await;
+    fail_file_changes(ctx, "INSERT");
+    let copy = BucketCopy {
+        bucket: target.to_string(),
+        prefix: None,
+        overwrite: false,
+    };
+
+    // Act
+    let response = copy_bucket(ctx, bucket, &copy).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+async fn physical_size(ctx: &BstoreAsyncContext) -> i64 {
+    let uri = format!("http://localhost:{}/api/stats", ctx.port);
+    let stats: Stats = reqwest::get(uri).await.unwrap().json().await.unwrap();
+    stats.physical_size
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn copy_file_within_bucket(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let bucket = Uuid::new_v4();
+    let id = insert_content(ctx, bucket, "f1", "content").await;
+    let stored = physical_size(ctx).await;
+    let copy = FileCopy {
+        bucket: None,
+        path: "d1/f2".to_string(),
+        overwrite: false,
+    };
+
+    // Act
+    let response = copy_file(ctx, id, &copy).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let copied: Vec<i64> = response.json().await.unwrap();
+    assert_ne!(copied[0], id);
+    assert_eq!(
+        content_hash(ctx, copied[0]).await,
+        content_hash(ctx, id).await
+    );
+    let uri = format!("http://localhost:{}/api/{bucket}/d1/f2", ctx.port);
+    let content = reqwest::get(uri).await.unwrap().text().await.unwrap();
+    assert_eq!(content, "content");
+    let uri = format!("http://localhost:{}/api/{bucket}/f1", ctx.port);
+    let source = reqwest::get(uri).await.unwrap();
+    assert_eq!(source.status(), StatusCode::OK);
+    assert_eq!(physical_size(ctx).await, stored);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn copy_file_conflict(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let bucket = Uuid::new_v4();
+    let target = Uuid::new_v4();
+    let id = insert_content(ctx, bucket, "f1", "content").await;
+    insert_content(ctx, target, "f1", "other").await;
+    let copy = FileCopy {
+        bucket: Some(target.to_string()),
+        path: "f1".to_string(),
+        overwrite: false,
+    };
+
+    // Act
+    let response = copy_file(ctx, id, &copy).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let uri = format!("http://localhost:{}/api/{target}/f1", ctx.port);
+    let content = reqwest::get(uri).await.unwrap().text().await.unwrap();
+    assert_eq!(content, "other");
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn copy_file_overwrite(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let bucket = Uuid::new_v4();
+    let target = Uuid::new_v4();
+    let id = insert_content(ctx, bucket, "f1", "content").await;
+    let existing = insert_content(ctx, target, "f1", "other").await;
+    let copy = FileCopy {
+        bucket: Some(target.to_string()),
+        path: "f1".to_string(),
+        overwrite: true,
+    };
+
+    // Act
+    let response = copy_file(ctx, id, &copy).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let copied: Vec<i64> = response.json().await.unwrap();
+    assert_eq!(copied, vec![existing]);
+    let uri = format!("http://localhost:{}/api/{target}/f1", ctx.port);
+    let content = reqwest::get(uri).await.unwrap().text().await.unwrap();
+    assert_eq!(content, "content");
+    // Overwritten content isn't used anymore
+    assert_eq!(physical_size(ctx).await, "content".len() as i64);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn copy_file_empty_path(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let bucket = Uuid::new_v4();
+    let id = insert_content(ctx, bucket, "f1", "content").await;
+    let copy = FileCopy {
+        bucket: None,
+        path: String::new(),
+        overwrite: false,
+    };
+
+    // Act
+    let response = copy_file(ctx, id, &copy).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let uri = format!("http://localhost:{}/api/{bucket}", ctx.port);
+    let files: Vec<FileItem> = reqwest::get(uri).await.unwrap().json().await.unwrap();
+    assert_eq!(files.len(), 1);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn copy_file_into_itself(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let bucket = Uuid::new_v4();
+    let id = insert_content(ctx, bucket, "f1", "content").await;
+    let copy = FileCopy {
+        bucket: Some(bucket.to_string()),
+        path: "f1".to_string(),
+        overwrite: true,
+    };
+
+    // Act
+    let response = copy_file(ctx, id, &copy).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let versions: Vec<FileVersion> = reqwest::get(format!(
+        "http://localhost:{}/api/file/{id}/versions",
+        ctx.port
+    ))
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+    assert_eq!(versions.len(), 1);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn copy_file_not_found(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let copy = FileCopy {
+        bucket: None,
+        path: "f1".to_string(),
+        overwrite: false,
+    };
+
+    // Act
+    let response = copy_file(ctx, 100_000, &copy).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn copy_bucket_with_prefix(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let bucket = Uuid::new_v4();
+    let target = Uuid::new_v4();
+    insert_content(ctx, bucket, "d1/f1", "content1").await;
+    insert_content(ctx, bucket, "d1/f2", "content2").await;
+    insert_content(ctx, bucket, "d2/f3", "content3").await;
+    insert_content(ctx, target, "d1/f2", "other").await;
+    let stored = physical_size(ctx).await;
+    let copy = BucketCopy {
+        bucket: target.to_string(),
+        prefix: Some("d1/".to_string()),
+        overwrite: false,
+    };
+
+    // Act
+    let response = copy_bucket(ctx, bucket, &copy).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let copied: Vec<i64> = response.json().await.unwrap();
+    assert_eq!(copied.len(), 1);
+    let uri = format!("http://localhost:{}/api/{target}/d1/f1", ctx.port);
+    let content = reqwest::get(uri).await.unwrap().text().await.unwrap();
+    assert_eq!(content, "content1");
+    let uri = format!("http://localhost:{}/api/{target}/d1/f2", ctx.port);
+    let content = reqwest::get(uri).await.unwrap().text().await.unwrap();
+    assert_eq!(content, "other");
+    let uri = format!("http://localhost:{}/api/{target}/d2/f3", ctx.port);
+    let missing = reqwest::get(uri).await.unwrap();
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+    assert_eq!(physical_size(ctx).await, stored);
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn copy_bucket_overwrite(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let bucket = Uuid::new_v4();
+    let target = Uuid::new_v4();
+    insert_content(ctx, bucket, "f1", "content1").await;
+    insert_content(ctx, bucket, "f2", "content2").await;
+    insert_content(ctx, target, "f2", "other").await;
+    let copy = BucketCopy {
+        bucket: target.to_string(),
+        prefix: None,
+        overwrite: true,
+    };
+
+    // Act
+    let response = copy_bucket(ctx, bucket, &copy).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let copied: Vec<i64> = response.json().await.unwrap();
+    assert_eq!(copied.len(), 2);
+    let uri = format!("http://localhost:{}/api/{target}/f2", ctx.port);
+    let content = reqwest::get(uri).await.unwrap().text().await.unwrap();
+    assert_eq!(content, "content2");
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn copy_bucket_into_itself(ctx: &mut BstoreAsyncContext) {
+    // Arrange
+    let bucket = Uuid::new_v4();
+    insert_content(ctx, bucket, "f1", "content").await;
+    let copy = BucketCopy {
+        bucket: bucket.to_string(),
+        prefix: None,
+        overwrite: true,
+    };
+
+    // Act
+    let response = copy_bucket(ctx, bucket, &copy).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+/// Asserts that files cannot be written into bucket with the name either directly
+/// or by linking, moving or copying
+async fn assert_bucket_reserved(ctx: &BstoreAsyncContext, name: &str) {
+    let client = Client::new();
+    let source = Uuid::new_v4();
+    let id = insert_content(ctx, source, "f1.txt", "content").await;
+    let hash = content_hash(ctx, id).await;
+    let link = BlobLink {
+        bucket: name.to_string(),
+        path: "f1.txt".to_string(),
+        content_type: None,
+    };
+    let destination = Destination {
+        bucket: Some(name.to_string()),
+        path: "f1.txt".to_string(),
+    };
+    let copy = BucketCopy {
+        bucket: name.to_string(),
+        prefix: None,
+        overwrite: false,
+    };
+
+    let linked = client
+        .post(format!(
+            "http://localhost:{}/api/blob/{hash}/files",
+            ctx.port
+        ))
+        .json(&link)
+        .send()
+        .await
+        .unwrap();
+    let tarred = client
+        .post(format!(
+            "http://localhost:{}/api/bucket/{name}/tar",
+            ctx.port
+        ))
+        .body(tar_dir(&ctx.root).await)
+        .send()
+        .await
+        .unwrap();
+    let moved = move_file(ctx, id, &destination).await;
+    let copied = copy_bucket(ctx, source, &copy).await;
+
+    assert_eq!(linked.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(tarred.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(moved.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(copied.status(), StatusCode::BAD_REQUEST);
+    let buckets: Vec<Bucket> = client
+        .get(format!("http://localhost:{}/api/", ctx.port))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(buckets.iter().all(|b| b.id != name));
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn file_bucket_reserved(ctx: &mut BstoreAsyncContext) {
+    assert_bucket_reserved(ctx, "file").await;
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn blob_bucket_reserved(ctx: &mut BstoreAsyncContext) {
+    assert_bucket_reserved(ctx, "blob").await;
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn stats_bucket_reserved(ctx: &mut BstoreAsyncContext) {
+    assert_bucket_reserved(ctx, "stats").await;
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn bucket_bucket_reserved(ctx: &mut BstoreAsyncContext) {
+    assert_bucket_reserved(ctx, "bucket").await;
+}
+
+#[test_context(BstoreAsyncContext)]
+#[tokio::test]
+#[serial]
+async fn meta_bucket_reserved(ctx: &mut BstoreAsyncContext) {
+    assert_bucket_reserved(ctx, "meta").await;
+}